@@ -1,13 +1,305 @@
+use std::fmt;
+
 use crate::scanner::Token;
 
-#[derive(Debug, Clone)]
+/// Depth below which `Debug` output elides subtrees. Use `{:#?}` to see
+/// everything.
+const DEBUG_MAX_DEPTH: usize = 4;
+
+/// Elements of a list node shown by the compact `Debug` form before the rest are
+/// counted instead.
+const DEBUG_MAX_ELEMENTS: usize = 8;
+
+#[derive(Clone, PartialEq)]
 pub enum Expr {
     Literal(Token),
     Unary(Token, Box<Expr>),
     Binary(Box<Expr>, Token, Box<Expr>),
     Grouping(Box<Expr>),
+    ArrayLiteral(Vec<Expr>),
+    /// `{ key: value, .. }`, where each key is a string, number or identifier token.
+    /// Identifier keys stand for the string of their name.
+    MapLiteral(Vec<(Token, Expr)>),
+    /// `target[index]`, keeping the `[` token for error positions.
+    Index(Box<Expr>, Token, Box<Expr>),
+    /// `condition ? then : else`
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// An interpolated string: the literal pieces, one more than the expressions
+    /// spliced between them.
+    Interpolation(Vec<Token>, Vec<Expr>),
+}
+
+impl Expr {
+    fn node_count(&self) -> usize {
+        match self {
+            Expr::Literal(_) => 1,
+            Expr::Unary(_, expr) | Expr::Grouping(expr) => 1 + expr.node_count(),
+            Expr::Binary(lhs, _, rhs) => 1 + lhs.node_count() + rhs.node_count(),
+            Expr::ArrayLiteral(elements) => {
+                1 + elements.iter().map(Expr::node_count).sum::<usize>()
+            }
+            Expr::MapLiteral(entries) => {
+                1 + entries
+                    .iter()
+                    .map(|(_, value)| value.node_count())
+                    .sum::<usize>()
+            }
+            Expr::Index(target, _, index) => 1 + target.node_count() + index.node_count(),
+            Expr::Ternary(condition, then, otherwise) => {
+                1 + condition.node_count() + then.node_count() + otherwise.node_count()
+            }
+            Expr::Interpolation(_, exprs) => 1 + exprs.iter().map(Expr::node_count).sum::<usize>(),
+        }
+    }
+
+    /// Levels in the tree, counting this node.
+    pub fn height(&self) -> usize {
+        1 + match self {
+            Expr::Literal(_) => 0,
+            Expr::Unary(_, expr) | Expr::Grouping(expr) => expr.height(),
+            Expr::Binary(lhs, _, rhs) | Expr::Index(lhs, _, rhs) => lhs.height().max(rhs.height()),
+            Expr::ArrayLiteral(elements) => elements.iter().map(Expr::height).max().unwrap_or(0),
+            Expr::MapLiteral(entries) => entries
+                .iter()
+                .map(|(_, value)| value.height())
+                .max()
+                .unwrap_or(0),
+            Expr::Ternary(condition, then, otherwise) => condition
+                .height()
+                .max(then.height())
+                .max(otherwise.height()),
+            Expr::Interpolation(_, exprs) => exprs.iter().map(Expr::height).max().unwrap_or(0),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Expr::Literal(_) => "Literal",
+            Expr::Unary(..) => "Unary",
+            Expr::Binary(..) => "Binary",
+            Expr::Grouping(_) => "Grouping",
+            Expr::ArrayLiteral(_) => "ArrayLiteral",
+            Expr::MapLiteral(_) => "MapLiteral",
+            Expr::Index(..) => "Index",
+            Expr::Ternary(..) => "Ternary",
+            Expr::Interpolation(..) => "Interpolation",
+        }
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            fmt_expr(self, f, None)
+        } else {
+            fmt_expr(self, f, Some(0))
+        }
+    }
+}
+
+/// Debug wrapper that tracks the current depth, or `None` when printing in full.
+struct Limited<'a>(&'a Expr, Option<usize>);
+
+impl fmt::Debug for Limited<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_expr(self.0, f, self.1)
+    }
+}
+
+/// The compact form shows only token kinds; positions are left to `{:#?}`.
+struct TokenDebug<'a>(&'a Token, bool);
+
+impl fmt::Debug for TokenDebug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.1 {
+            self.0.fmt(f)
+        } else {
+            self.0.kind.fmt(f)
+        }
+    }
+}
+
+/// Stands in for the elements left out of a long list node.
+struct More(usize);
+
+impl fmt::Debug for More {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "…({} more)", self.0)
+    }
+}
+
+fn fmt_expr(expr: &Expr, f: &mut fmt::Formatter<'_>, depth: Option<usize>) -> fmt::Result {
+    let token = |token| TokenDebug(token, depth.is_none());
+
+    if depth.is_some_and(|depth| depth >= DEBUG_MAX_DEPTH) {
+        return match expr {
+            Expr::Literal(literal) => f.debug_tuple("Literal").field(&token(literal)).finish(),
+            _ => write!(
+                f,
+                "{} {{ .. {} more nodes .. }}",
+                expr.name(),
+                expr.node_count() - 1
+            ),
+        };
+    }
+
+    let next = depth.map(|depth| depth + 1);
+    let shown = if depth.is_some() {
+        DEBUG_MAX_ELEMENTS
+    } else {
+        usize::MAX
+    };
+    let mut tuple = f.debug_tuple(expr.name());
+
+    match expr {
+        Expr::Literal(literal) => tuple.field(&token(literal)),
+        Expr::Unary(operator, expr) => tuple.field(&token(operator)).field(&Limited(expr, next)),
+        Expr::Binary(lhs, operator, rhs) => tuple
+            .field(&Limited(lhs, next))
+            .field(&token(operator))
+            .field(&Limited(rhs, next)),
+        Expr::Grouping(expr) => tuple.field(&Limited(expr, next)),
+        Expr::ArrayLiteral(elements) => {
+            for element in elements.iter().take(shown) {
+                tuple.field(&Limited(element, next));
+            }
+
+            if elements.len() > shown {
+                tuple.field(&More(elements.len() - shown));
+            }
+
+            &mut tuple
+        }
+        Expr::MapLiteral(entries) => {
+            for (key, value) in entries.iter().take(shown) {
+                tuple.field(&token(key)).field(&Limited(value, next));
+            }
+
+            if entries.len() > shown {
+                tuple.field(&More(entries.len() - shown));
+            }
+
+            &mut tuple
+        }
+        Expr::Index(target, bracket, index) => tuple
+            .field(&Limited(target, next))
+            .field(&token(bracket))
+            .field(&Limited(index, next)),
+        Expr::Ternary(condition, then, otherwise) => tuple
+            .field(&Limited(condition, next))
+            .field(&Limited(then, next))
+            .field(&Limited(otherwise, next)),
+        Expr::Interpolation(pieces, exprs) => {
+            for (i, piece) in pieces.iter().enumerate().take(shown) {
+                tuple.field(&token(piece));
+
+                if let Some(expr) = exprs.get(i) {
+                    tuple.field(&Limited(expr, next));
+                }
+            }
+
+            // Counts pieces; the expressions between them are left out with them.
+            if pieces.len() > shown {
+                tuple.field(&More(pieces.len() - shown));
+            }
+
+            &mut tuple
+        }
+    };
+
+    tuple.finish()
 }
 
 pub trait Visitor<T> {
     fn visit_expr(&self, expr: &Expr) -> T;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Span;
+    use crate::scanner::TokenKind;
+
+    fn token(kind: TokenKind) -> Token {
+        let lexeme = kind.to_string();
+        Token::new(kind, lexeme, Span::default(), 1, 1)
+    }
+
+    fn nested_negation(depth: usize) -> Expr {
+        (0..depth).fold(Expr::Literal(token(TokenKind::Number(1.0))), |expr, _| {
+            Expr::Unary(token(TokenKind::Minus), Box::new(expr))
+        })
+    }
+
+    #[test]
+    fn test_debug_truncates_deep_trees() {
+        let expr = nested_negation(100);
+
+        assert_eq!(
+            format!("{expr:?}"),
+            "Unary(Minus, Unary(Minus, Unary(Minus, Unary(Minus, \
+             Unary { .. 96 more nodes .. }))))"
+        );
+        assert_eq!(format!("{expr:#?}").matches("Unary").count(), 100);
+    }
+
+    #[test]
+    fn test_debug_alternate_shows_small_trees() {
+        let expr = Expr::Binary(
+            Box::new(Expr::Literal(token(TokenKind::Number(1.0)))),
+            token(TokenKind::Plus),
+            Box::new(Expr::Literal(token(TokenKind::Number(2.0)))),
+        );
+
+        assert_eq!(
+            format!("{expr:?}"),
+            "Binary(Literal(Number(1.0)), Plus, Literal(Number(2.0)))"
+        );
+        let full = format!("{expr:#?}");
+        assert!(full.contains("kind: Plus,"));
+        assert!(full.contains("lexeme: \"2\","));
+    }
+
+    #[test]
+    fn test_debug_truncates_long_arrays() {
+        let elements = (0..10_000)
+            .map(|n| Expr::Literal(token(TokenKind::Number(n as f64))))
+            .collect();
+        let expr = Expr::ArrayLiteral(elements);
+
+        assert_eq!(
+            format!("{expr:?}"),
+            "ArrayLiteral(Literal(Number(0.0)), Literal(Number(1.0)), \
+             Literal(Number(2.0)), Literal(Number(3.0)), Literal(Number(4.0)), \
+             Literal(Number(5.0)), Literal(Number(6.0)), Literal(Number(7.0)), \
+             …(9992 more))"
+        );
+        assert_eq!(format!("{expr:#?}").matches("Number(").count(), 10_000);
+    }
+
+    #[test]
+    fn test_debug_truncates_long_maps_and_interpolations() {
+        let number = |n: usize| Expr::Literal(token(TokenKind::Number(n as f64)));
+
+        let entries = (0..10_000)
+            .map(|n| (token(TokenKind::Ident(format!("k{n}"))), number(n)))
+            .collect();
+        let expr = Expr::MapLiteral(entries);
+        let compact = format!("{expr:?}");
+
+        assert_eq!(compact.matches("Ident").count(), 8);
+        assert!(compact.ends_with("Ident(\"k7\"), Literal(Number(7.0)), …(9992 more))"));
+        assert_eq!(format!("{expr:#?}").matches("Ident").count(), 10_000);
+
+        let pieces = (0..10_000)
+            .map(|_| token(TokenKind::Interpolation("s".to_string())))
+            .collect();
+        let exprs = (0..9_999).map(number).collect();
+        let expr = Expr::Interpolation(pieces, exprs);
+        let compact = format!("{expr:?}");
+
+        assert_eq!(compact.matches("Interpolation(\"s\")").count(), 8);
+        assert!(compact.ends_with("Literal(Number(7.0)), …(9992 more))"));
+        assert_eq!(format!("{expr:#?}").matches("Number(").count(), 9_999);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::scanner;
+use crate::Error;
+
+/// Longest piece of source echoed back inside an error message.
+const MAX_QUOTED_CHARS: usize = 32;
+
+/// Longest source line shown in an error before it's cut down around the error,
+/// unless `--max-line-width` says otherwise.
+pub const DEFAULT_LINE_WIDTH: usize = 120;
+
+/// Words from other languages that users commonly type in place of a Lox keyword.
+const ALIASES: &[(&str, &str)] = &[
+    ("null", "nil"),
+    ("none", "nil"),
+    ("undefined", "nil"),
+    ("elif", "else if"),
+    ("elseif", "else if"),
+    ("function", "fun"),
+    ("def", "fun"),
+    ("fn", "fun"),
+    ("let", "var"),
+    ("const", "var"),
+    ("self", "this"),
+];
+
+/// Suggests the keyword an identifier was probably meant to be, either because it
+/// matches a keyword case-insensitively (`True`) or is a known alias (`null`).
+pub fn suggest_keyword(name: &str) -> Option<&'static str> {
+    let lower = name.to_ascii_lowercase();
+
+    if let Some((_, keyword)) = ALIASES.iter().find(|(alias, _)| *alias == lower) {
+        return Some(keyword);
+    }
+
+    scanner::keyword_names().find(|keyword| *keyword != name && *keyword == lower)
+}
+
+/// Appends a "did you mean" hint to `message` if `name` looks like a mistyped keyword.
+pub fn with_keyword_suggestion(message: String, name: &str) -> String {
+    match suggest_keyword(name) {
+        Some(keyword) => format!("{message} (did you mean '{keyword}'?)"),
+        None => message,
+    }
+}
+
+/// Quotes a piece of source for an error message, cutting it short if it's long
+/// enough to swamp the message, e.g. `'aaaa…' (truncated, 10000 chars)`.
+pub fn quote(text: &str) -> String {
+    let len = text.chars().count();
+
+    if len <= MAX_QUOTED_CHARS {
+        return format!("'{text}'");
+    }
+
+    let prefix = text.chars().take(MAX_QUOTED_CHARS).collect::<String>();
+    format!("'{prefix}…' (truncated, {len} chars)")
+}
+
+/// Cuts a line longer than `max_width` chars down to the part around `column`
+/// (1-based, in chars), marking whatever was dropped from either end with `…`.
+pub fn excerpt(line: &str, column: Option<usize>, max_width: usize) -> String {
+    let len = line.chars().count();
+    let (start, end) = window(
+        len,
+        column.map_or(0, |column| column.saturating_sub(1)),
+        max_width,
+    );
+
+    let mut s = line
+        .chars()
+        .skip(start)
+        .take(end - start)
+        .collect::<String>();
+
+    if start > 0 {
+        s.insert(0, '…');
+    }
+
+    if end < len {
+        s.push('…');
+    }
+
+    s
+}
+
+/// Renders an error rustc-style: the message, its position, then the source line
+/// with `^`s under the `width` chars starting at `column`. Tabs are expanded to
+/// `tab_width` so the carets stay aligned, and long lines are windowed as in
+/// [`excerpt`].
+pub fn snippet(
+    message: &str,
+    line_number: usize,
+    column: Option<usize>,
+    line: &str,
+    width: usize,
+    tab_width: usize,
+    max_width: usize,
+) -> String {
+    let gutter = " ".repeat(line_number.to_string().len());
+    let Some(column) = column else {
+        return format!(
+            "error: {message}\n{gutter}--> line {line_number}\n{gutter} |\n{line_number} | {}",
+            expand_tabs(&excerpt(line, None, max_width), tab_width)
+        );
+    };
+
+    let chars = line.chars().collect::<Vec<_>>();
+    let first = (column - 1).min(chars.len());
+    let last = (first + width.max(1)).min(chars.len());
+    let (start, end) = window(chars.len(), first, max_width);
+
+    let mut text = String::new();
+    let mut caret_start = None;
+    let mut caret_end = None;
+
+    if start > 0 {
+        text.push('…');
+    }
+
+    for (i, &c) in chars.iter().enumerate().take(end).skip(start) {
+        let display = text.chars().count();
+
+        if i == first {
+            caret_start = Some(display);
+        }
+        if i == last {
+            caret_end = Some(display);
+        }
+
+        push_expanded(&mut text, c, tab_width);
+    }
+
+    let display = text.chars().count();
+    let caret_start = caret_start.unwrap_or(display);
+    let caret_end = caret_end.unwrap_or(display).max(caret_start + 1);
+
+    if end < chars.len() {
+        text.push('…');
+    }
+
+    format!(
+        "error: {message}\n{gutter}--> {line_number}:{column}\n{gutter} |\n{line_number} | {text}\n{gutter} | {}{}",
+        " ".repeat(caret_start),
+        "^".repeat(caret_end - caret_start)
+    )
+}
+
+/// The chars of a `len`-char line shown around the char at `center`, as a range
+/// at most `max_width` chars long.
+fn window(len: usize, center: usize, max_width: usize) -> (usize, usize) {
+    if len <= max_width {
+        return (0, len);
+    }
+
+    let start = center.saturating_sub(max_width / 2).min(len - max_width);
+
+    (start, start + max_width)
+}
+
+fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let mut s = String::new();
+
+    for c in line.chars() {
+        push_expanded(&mut s, c, tab_width);
+    }
+
+    s
+}
+
+/// Pushes `c`, or for a tab enough spaces to reach the next tab stop.
+fn push_expanded(s: &mut String, c: char, tab_width: usize) {
+    if c == '\t' {
+        let tab_width = tab_width.max(1);
+        s.push_str(&" ".repeat(tab_width - s.chars().count() % tab_width));
+    } else {
+        s.push(c);
+    }
+}
+
+/// Puts errors in source order and drops exact repeats, i.e. the same message at
+/// the same span. Errors without a position go after those with one. Errors at
+/// the same span are ordered by message so repeats end up next to each other.
+pub fn tidy(errors: &mut Vec<Error>) {
+    errors.sort_by_cached_key(|err| (position(err), err.to_string()));
+    errors.dedup_by(|a, b| position(a) == position(b) && a.to_string() == b.to_string());
+}
+
+fn position(err: &Error) -> (usize, usize, usize) {
+    match err {
+        Error::InterpretErrorWithIndex {
+            line_index,
+            column,
+            span,
+            ..
+        } => (
+            *line_index,
+            column.unwrap_or(0),
+            span.map_or(0, |span| span.start),
+        ),
+        Error::ParseError {
+            line, column, span, ..
+        } => (*line, *column, span.start),
+        _ => (usize::MAX, 0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Span;
+
+    #[test]
+    fn test_suggest_keyword() {
+        assert_eq!(suggest_keyword("null"), Some("nil"));
+        assert_eq!(suggest_keyword("NULL"), Some("nil"));
+        assert_eq!(suggest_keyword("elif"), Some("else if"));
+        assert_eq!(suggest_keyword("Function"), Some("fun"));
+        assert_eq!(suggest_keyword("True"), Some("true"));
+        assert_eq!(suggest_keyword("If"), Some("if"));
+        assert_eq!(suggest_keyword("iff"), None);
+        assert_eq!(suggest_keyword("count"), None);
+    }
+
+    #[test]
+    fn test_excerpt() {
+        assert_eq!(excerpt("1 + @", Some(5), 80), "1 + @");
+
+        let line = (0..100)
+            .map(|i| char::from(b'a' + i % 26))
+            .collect::<String>();
+        assert_eq!(excerpt(&line, None, 80), format!("{}…", &line[..80]));
+        assert_eq!(excerpt(&line, Some(1), 80), format!("{}…", &line[..80]));
+        assert_eq!(excerpt(&line, Some(50), 80), format!("…{}…", &line[9..89]));
+        assert_eq!(excerpt(&line, Some(100), 80), format!("…{}", &line[20..]));
+        assert_eq!(excerpt(&line, Some(50), DEFAULT_LINE_WIDTH), line);
+        assert_eq!(excerpt(&line, Some(50), 10), format!("…{}…", &line[44..54]));
+    }
+
+    #[test]
+    fn test_tidy() {
+        let error = |offset: usize, message: &str| {
+            Error::message_with_span(Span::new(offset, offset + 1), 1, offset + 1, "", message)
+        };
+        let mut errors = vec![
+            error(4, "b"),
+            error(0, "a"),
+            Error::message("", "no position"),
+            error(4, "b"),
+            error(4, "c"),
+            error(4, "b"),
+        ];
+
+        tidy(&mut errors);
+
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "Error interpreting line [1:1] \"\": a",
+                "Error interpreting line [1:5] \"\": b",
+                "Error interpreting line [1:5] \"\": c",
+                "Error interpreting line \"\": no position",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("name"), "'name'");
+        assert_eq!(quote(&"é".repeat(32)), format!("'{}'", "é".repeat(32)));
+        assert_eq!(
+            quote(&"a".repeat(10_000)),
+            format!("'{}…' (truncated, 10000 chars)", "a".repeat(32))
+        );
+    }
+}
@@ -1,6 +1,8 @@
 mod ast;
+mod diagnostics;
 mod parser;
 mod print;
+mod scan_diff;
 mod scanner;
 
 use std::fs::OpenOptions;
@@ -9,11 +11,21 @@ use std::io::Read;
 use std::io::Write;
 use std::num;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process;
 use std::result;
 
 use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
 
+use self::ast::Expr;
+use self::ast::Visitor;
+use self::print::PrettyPrinter;
+use self::print::SourcePrinter;
 use self::scanner::Scanner;
+use self::scanner::Span;
+use self::scanner::Token;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -21,17 +33,42 @@ pub enum Error {
     Io(#[from] io::Error),
     #[error(transparent)]
     ParseNumber(#[from] num::ParseFloatError),
-    #[error("Error interpreting line \"{line_string}\": {message}")]
+    #[error(
+        "Error interpreting line \"{}\": {message}",
+        diagnostics::excerpt(line_string, None, diagnostics::DEFAULT_LINE_WIDTH)
+    )]
     InterpretError {
         line_string: String,
         message: String,
     },
-    #[error("Error interpreting line [{line_index}] \"{line_string}\": {message}")]
+    #[error(
+        "Error interpreting line [{}] \"{}\": {message}",
+        position(*line_index, *column),
+        diagnostics::excerpt(line_string, *column, diagnostics::DEFAULT_LINE_WIDTH)
+    )]
     InterpretErrorWithIndex {
         line_index: usize,
+        column: Option<usize>,
+        span: Option<Span>,
         line_string: String,
         message: String,
     },
+    #[error("{}", lines(errors, *omitted))]
+    ScanErrors { errors: Vec<Error>, omitted: usize },
+    #[error("Error parsing expression at {line}:{column}: {message}")]
+    ParseError {
+        line: usize,
+        column: usize,
+        span: Span,
+        message: String,
+    },
+    #[error("{artifact} has {}, but this build reads version {FORMAT_VERSION}", version(*found))]
+    FormatVersion {
+        artifact: &'static str,
+        found: Option<u32>,
+    },
+    #[error("{artifact} is malformed at line {line}; rerun with --update to replace it")]
+    Malformed { artifact: &'static str, line: usize },
 }
 
 impl Error {
@@ -45,10 +82,157 @@ impl Error {
     pub fn message_with_line_index(line_index: usize, line_str: &str, message: &str) -> Self {
         Error::InterpretErrorWithIndex {
             line_index,
+            column: None,
+            span: None,
+            line_string: line_str.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn message_with_span(
+        span: Span,
+        line_index: usize,
+        column: usize,
+        line_str: &str,
+        message: &str,
+    ) -> Self {
+        Error::InterpretErrorWithIndex {
+            line_index,
+            column: Some(column),
+            span: Some(span),
             line_string: line_str.to_string(),
             message: message.to_string(),
         }
     }
+
+    /// Keeps the first `max` of several scan errors, counting the rest so they can
+    /// be summarized instead of listed.
+    pub fn truncated(self, max: usize) -> Self {
+        match self {
+            Error::ScanErrors {
+                mut errors,
+                omitted,
+            } if errors.len() > max => {
+                let extra = errors.len() - max;
+                errors.truncate(max);
+
+                Error::ScanErrors {
+                    errors,
+                    omitted: omitted + extra,
+                }
+            }
+            err => err,
+        }
+    }
+
+    /// Renders the error for a terminal, underlining its position in `source` and
+    /// cutting source lines down to `max_width` chars.
+    pub fn render(&self, source: &str, tab_width: usize, max_width: usize) -> String {
+        let width = |span: &Span| {
+            source
+                .get(span.start..span.end)
+                .map_or(1, |text| text.chars().take_while(|&c| c != '\n').count())
+        };
+
+        match self {
+            Error::InterpretError {
+                line_string,
+                message,
+            } => format!(
+                "error: {message}\n  | {}",
+                diagnostics::excerpt(
+                    line_string.lines().next().unwrap_or_default(),
+                    None,
+                    max_width,
+                )
+            ),
+            Error::InterpretErrorWithIndex {
+                line_index,
+                column,
+                span,
+                line_string,
+                message,
+            } => diagnostics::snippet(
+                message,
+                *line_index,
+                *column,
+                line_string,
+                span.as_ref().map_or(1, width),
+                tab_width,
+                max_width,
+            ),
+            Error::ScanErrors { errors, omitted } => errors
+                .iter()
+                .map(|err| err.render(source, tab_width, max_width))
+                .chain(more_errors(*omitted))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            Error::ParseError {
+                line,
+                column,
+                span,
+                message,
+            } => diagnostics::snippet(
+                message,
+                *line,
+                Some(*column),
+                source.lines().nth(line - 1).unwrap_or_default(),
+                width(span),
+                tab_width,
+                max_width,
+            ),
+            err => format!("error: {err}"),
+        }
+    }
+
+    pub fn parse(token: &Token, message: &str) -> Self {
+        Error::ParseError {
+            line: token.line,
+            column: token.column,
+            span: token.span,
+            message: message.to_string(),
+        }
+    }
+}
+
+fn position(line: usize, column: Option<usize>) -> String {
+    match column {
+        Some(column) => format!("{line}:{column}"),
+        None => line.to_string(),
+    }
+}
+
+fn lines(errors: &[Error], omitted: usize) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .chain(more_errors(omitted))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The line summarizing errors left out of a report, if any were.
+fn more_errors(omitted: usize) -> Option<String> {
+    match omitted {
+        0 => None,
+        1 => Some("and 1 more error".to_string()),
+        n => Some(format!("and {n} more errors")),
+    }
+}
+
+/// Version of every machine-readable format this crate writes, such as the
+/// scan-diff cache and the `--emit tokens` dump. Bump it whenever one of them
+/// changes.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Starts the first line of every versioned output, followed by `FORMAT_VERSION`.
+pub const VERSION_HEADER: &str = "# format version: ";
+
+fn version(found: Option<u32>) -> String {
+    match found {
+        Some(found) => format!("format version {found}"),
+        None => "no format version".to_string(),
+    }
 }
 
 type Result<T> = result::Result<T, Error>;
@@ -56,38 +240,113 @@ type Result<T> = result::Result<T, Error>;
 #[derive(Parser, Debug)]
 #[command(version, about)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long)]
     file: Option<String>,
+    /// Treat a newline inside a string literal as an unterminated string
+    #[arg(long)]
+    no_multiline_strings: bool,
+    /// Lines an unterminated string must span before the error guesses where its
+    /// closing quote went missing
+    #[arg(long, default_value_t = 3)]
+    long_string_lines: usize,
+    /// Most scan errors to list before summarizing the rest
+    #[arg(long, default_value_t = 20)]
+    max_errors: usize,
+    /// Columns between tab stops when showing source lines in errors
+    #[arg(long, default_value_t = 4)]
+    tab_width: usize,
+    /// Longest source line shown in errors before it's cut down around the error
+    #[arg(long, default_value_t = diagnostics::DEFAULT_LINE_WIDTH)]
+    max_line_width: usize,
+    /// What to print for each parsed expression
+    #[arg(long, value_enum, default_value_t = Emit::Ast)]
+    emit: Emit,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Tools for working on the interpreter itself
+    #[command(subcommand)]
+    Dev(DevCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum DevCommand {
+    /// Compare the token streams of every .lox file under a directory against a
+    /// cache, creating the cache on the first run
+    ScanDiff {
+        dir: PathBuf,
+        /// Only report changes to token kinds and lexemes, not line numbers
+        #[arg(long)]
+        ignore_positions: bool,
+        /// Rewrite the cache from the current scanner output
+        #[arg(long)]
+        update: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Emit {
+    /// Parenthesized prefix form of the syntax tree
+    Ast,
+    /// Lox source regenerated from the syntax tree
+    Source,
+    /// Every token the scanner produced, comments included, one per line after a
+    /// format version header
+    Tokens,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    if let Some(file) = &args.file {
-        run_file(file)?;
+    if let Some(Command::Dev(DevCommand::ScanDiff {
+        dir,
+        ignore_positions,
+        update,
+    })) = &args.command
+    {
+        let report = scan_diff::run(dir, *ignore_positions, *update)?;
+
+        if !report.is_empty() {
+            println!("{}", report.join("\n"));
+            process::exit(1);
+        }
+    } else if let Some(file) = &args.file {
+        run_file(file, &args)?;
     } else {
-        run_prompt()?;
+        run_prompt(&args)?;
     }
 
     Ok(())
 }
 
-fn run_file<P: AsRef<Path>>(path: P) -> Result<()> {
+fn run_file<P: AsRef<Path>>(path: P, args: &Args) -> Result<()> {
     let mut file = OpenOptions::new().read(true).open(path)?;
     let mut source = String::new();
 
     file.read_to_string(&mut source)?;
-    interpret(source)?;
+
+    if let Err(err) = interpret(source.clone(), args) {
+        eprintln!(
+            "{}",
+            err.render(&source, args.tab_width, args.max_line_width)
+        );
+        process::exit(1);
+    }
 
     Ok(())
 }
 
-fn run_prompt() -> Result<()> {
+fn run_prompt(args: &Args) -> Result<()> {
     print!("> ");
     io::stdout().flush()?;
 
-    for line in io::stdin().lines().flatten() {
-        interpret(line)?;
+    for line in io::stdin().lines().map_while(result::Result::ok) {
+        if let Err(err) = interpret(line.clone(), args) {
+            eprintln!("{}", err.render(&line, args.tab_width, args.max_line_width));
+        }
 
         print!("> ");
         io::stdout().flush()?;
@@ -96,9 +355,275 @@ fn run_prompt() -> Result<()> {
     Ok(())
 }
 
-fn interpret(source: String) -> Result<()> {
-    let tokens = Scanner::new(source).read_tokens()?;
-    println!("Found tokens: {tokens:?}");
+fn interpret(source: String, args: &Args) -> Result<()> {
+    let scanner = Scanner::new(source)
+        .with_multiline_strings(!args.no_multiline_strings)
+        .with_long_string_lines(args.long_string_lines);
+
+    let output = match args.emit {
+        Emit::Ast => parse_source(scanner).map(|expr| PrettyPrinter.visit_expr(&expr)),
+        Emit::Source => parse_source(scanner).map(|expr| SourcePrinter.visit_expr(&expr)),
+        Emit::Tokens => scanner
+            .with_trivia(true)
+            .read_tokens()
+            .map(|tokens| dump_tokens(&tokens)),
+    };
+
+    println!("{}", output.map_err(|err| err.truncated(args.max_errors))?);
 
     Ok(())
 }
+
+/// One line per token after the version header, for `--emit tokens`.
+fn dump_tokens(tokens: &[Token]) -> String {
+    let mut dump = format!("{VERSION_HEADER}{FORMAT_VERSION}");
+
+    for token in tokens {
+        dump.push_str(&format!(
+            "\n{}:{}\t{:?}\t{:?}",
+            token.line, token.column, token.kind, token.lexeme
+        ));
+    }
+
+    dump
+}
+
+fn parse_source(mut scanner: Scanner) -> Result<Expr> {
+    let tokens = scanner.read_tokens()?;
+    parser::Parser::new(tokens).parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Debug, PartialEq)]
+    enum Outcome {
+        ScanError,
+        ParseError,
+        Ok,
+    }
+
+    fn outcome(result: Result<Expr>) -> Outcome {
+        match result {
+            Ok(_) => Outcome::Ok,
+            Err(Error::ParseError { .. }) => Outcome::ParseError,
+            Err(_) => Outcome::ScanError,
+        }
+    }
+
+    fn regression_paths() -> Vec<PathBuf> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+        let mut paths = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert!(!paths.is_empty());
+        paths
+    }
+
+    fn file_name(path: &Path) -> String {
+        path.file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_regressions() {
+        for path in regression_paths() {
+            let name = file_name(&path);
+            let expected = if name.starts_with("scan_err_") {
+                Outcome::ScanError
+            } else if name.starts_with("parse_err_") {
+                Outcome::ParseError
+            } else if name.starts_with("ok_") {
+                Outcome::Ok
+            } else {
+                panic!("{name}: regression files must start with scan_err_, parse_err_ or ok_");
+            };
+
+            let source = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+            let (sender, receiver) = mpsc::channel();
+            // Match the main thread's stack so deep-nesting fixtures behave as they would
+            // when run from the command line.
+            thread::Builder::new()
+                .stack_size(8 * 1024 * 1024)
+                .spawn(move || sender.send(outcome(parse_source(Scanner::new(source)))))
+                .unwrap();
+
+            match receiver.recv_timeout(Duration::from_secs(1)) {
+                Ok(actual) => assert_eq!(actual, expected, "{name}"),
+                Err(mpsc::RecvTimeoutError::Timeout) => panic!("{name}: timed out"),
+                Err(mpsc::RecvTimeoutError::Disconnected) => panic!("{name}: panicked"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_outputs_start_with_format_version() {
+        let header = format!("{VERSION_HEADER}{FORMAT_VERSION}\n");
+        let tokens = Scanner::new("1 // one".to_string()).read_tokens().unwrap();
+
+        assert!(dump_tokens(&tokens).starts_with(&header));
+        assert!(scan_diff::write_cache(&scan_diff::Dumps::new()).starts_with(&header));
+    }
+
+    #[test]
+    fn test_scan_errors_truncated() {
+        let err = Scanner::new("$ @ # ` ~".to_string())
+            .read_tokens()
+            .unwrap_err();
+
+        assert_eq!(
+            err.truncated(2).to_string(),
+            "Error interpreting line [1:1] \"$ @ # ` ~\": Unexpected character '$'\n\
+             Error interpreting line [1:3] \"$ @ # ` ~\": Unexpected character '@'\n\
+             and 3 more errors"
+        );
+
+        let err = Scanner::new("$ @".to_string()).read_tokens().unwrap_err();
+        assert_eq!(
+            err.truncated(1).to_string().lines().last(),
+            Some("and 1 more error")
+        );
+
+        let err = Scanner::new("$ @".to_string()).read_tokens().unwrap_err();
+        assert_eq!(err.truncated(2).to_string().lines().count(), 2);
+    }
+
+    fn render(source: &str) -> String {
+        parse_source(Scanner::new(source.to_string()))
+            .unwrap_err()
+            .render(source, 4, diagnostics::DEFAULT_LINE_WIDTH)
+    }
+
+    #[test]
+    fn test_render_scan_error() {
+        assert_eq!(
+            render("1 +\n\t\"é✓\" + @ + 2"),
+            "error: Unexpected character '@'\n \
+             --> 2:9\n  \
+             |\n\
+             2 |     \"é✓\" + @ + 2\n  \
+             |            ^"
+        );
+        assert_eq!(
+            render("1 + \"abc"),
+            "error: Unterminated string starting at line 1, column 5: '\"abc'\n \
+             --> 1:5\n  \
+             |\n\
+             1 | 1 + \"abc\n  \
+             |     ^^^^"
+        );
+    }
+
+    #[test]
+    fn test_render_parse_error() {
+        assert_eq!(
+            render("1 +\n\n\n\n\n\n\n\n\n  ab\tnil"),
+            "error: Expected expression, found 'ab'\n  \
+             --> 10:3\n   \
+             |\n\
+             10 |   ab    nil\n   \
+             |   ^^"
+        );
+        assert_eq!(
+            render("(1 + 2"),
+            "error: Expected ')' after expression\n \
+             --> 1:7\n  \
+             |\n\
+             1 | (1 + 2\n  \
+             |       ^"
+        );
+    }
+
+    #[test]
+    fn test_render_error_at_end() {
+        let expected = "error: Expected expression, found end of input\n \
+                        --> 2:4\n  \
+                        |\n\
+                        2 | 1 +\n  \
+                        |    ^";
+
+        assert_eq!(render("\n1 +"), expected);
+        assert_eq!(render("\n1 +\n"), expected);
+        assert_eq!(render("\n1 +\n\n  \n"), expected);
+    }
+
+    #[test]
+    fn test_render_several_errors() {
+        let source = format!("{} $ ` ~", "1 +".repeat(100));
+        let rendered = Scanner::new(source.clone())
+            .read_tokens()
+            .unwrap_err()
+            .truncated(2)
+            .render(&source, 4, diagnostics::DEFAULT_LINE_WIDTH);
+        let blocks = rendered.split("\n\n").collect::<Vec<_>>();
+
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks[0].ends_with(&format!(
+            "1 | …{} $ ` ~\n  |{}^",
+            &"1 +".repeat(100)[186..],
+            " ".repeat(117)
+        )));
+        assert_eq!(blocks[2], "and 1 more error");
+    }
+
+    #[test]
+    fn test_render_far_column() {
+        let source = format!("{}@{}", "1".repeat(4999), "1".repeat(5000));
+        let args = Args::parse_from(["lox", "--max-line-width", "40"]);
+
+        for width in [diagnostics::DEFAULT_LINE_WIDTH, args.max_line_width] {
+            let rendered = Scanner::new(source.clone())
+                .read_tokens()
+                .unwrap_err()
+                .render(&source, 4, width);
+            let lines = rendered.lines().collect::<Vec<_>>();
+
+            assert_eq!(lines[1], " --> 1:5000");
+            let text = lines[3].strip_prefix("1 | ").unwrap();
+            let carets = lines[4].strip_prefix("  | ").unwrap();
+
+            assert!(text.starts_with('…') && text.ends_with('…'), "{text}");
+            assert_eq!(text.chars().count(), width + 2);
+            assert_eq!(carets.trim_start(), "^");
+            assert_eq!(text.chars().nth(carets.len() - 1), Some('@'));
+        }
+    }
+
+    #[test]
+    fn test_regressions_emit_source() {
+        let paths = regression_paths()
+            .into_iter()
+            .filter(|path| file_name(path).starts_with("ok_"));
+
+        for path in paths {
+            let source = fs::read_to_string(&path).unwrap();
+            // The printers recurse once per level of the tree, so long chains need the
+            // main thread's stack too.
+            let (emitted, reprinted, printed) = thread::Builder::new()
+                .stack_size(8 * 1024 * 1024)
+                .spawn(move || {
+                    let expr = parse_source(Scanner::new(source)).unwrap();
+                    let emitted = SourcePrinter.visit_expr(&expr);
+                    let reparsed = parse_source(Scanner::new(emitted.clone())).unwrap();
+
+                    (
+                        emitted,
+                        PrettyPrinter.visit_expr(&reparsed),
+                        PrettyPrinter.visit_expr(&expr),
+                    )
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+
+            assert_eq!(reprinted, printed, "{}: {emitted}", file_name(&path));
+        }
+    }
+}
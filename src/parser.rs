@@ -1,137 +1,481 @@
 use crate::ast::Expr;
+use crate::diagnostics;
+use crate::scanner::Span;
 use crate::scanner::Token;
+use crate::scanner::TokenKind;
+use crate::Error;
+use crate::Result;
+
+/// Maximum nesting of groupings and unary operators before the parser gives up,
+/// so pathological input is reported instead of overflowing the stack. Each level
+/// passes through every precedence rule, costing over 30 KiB of stack in debug
+/// builds, so this keeps well inside an 8 MiB main thread.
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// Maximum height of the tree built by an operator or index chain, operands
+/// included. Chains are parsed in a loop, but the printers and `Drop` recurse once
+/// per level at a few KiB each, so a long enough `1 + 1 + ...` would otherwise
+/// overflow the stack after parsing. Nodes outside chains add at most one level
+/// per nesting, which `MAX_NESTING_DEPTH` already bounds.
+const MAX_TREE_DEPTH: usize = 1024;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    depth: usize,
 }
 
 impl Parser {
+    /// Comments are dropped, so tokens scanned in trivia mode can be parsed as is.
     pub fn new(tokens: impl IntoIterator<Item = Token>) -> Self {
         Self {
-            tokens: tokens.into_iter().collect(),
+            tokens: tokens
+                .into_iter()
+                .filter(|token| !token.kind.is_trivia())
+                .collect(),
             current: 0,
+            depth: 0,
         }
     }
 
-    pub fn parse(&mut self) -> Expr {
-        self.parse_expression()
-    }
+    pub fn parse(&mut self) -> Result<Expr> {
+        if self.match_any([TokenKind::Eq]).is_some() {
+            return Err(self.error_at_previous(
+                "Unexpected '=' — assignment requires a variable name on the left",
+            ));
+        }
+
+        if self.match_any([TokenKind::EqEq]).is_some() {
+            return Err(self.error_at_previous("Unexpected '==' at start of statement"));
+        }
+
+        let expr = self.parse_expression()?;
 
-    fn parse_expression(&mut self) -> Expr {
-        self.parse_equality()
+        if let Some(token) = self.peek().filter(|_| !self.is_at_end()) {
+            return Err(self.error(&format!(
+                "Unexpected token {} after expression",
+                diagnostics::quote(&token.to_string())
+            )));
+        }
+
+        Ok(expr)
     }
 
-    fn parse_equality(&mut self) -> Expr {
-        let mut expr = self.parse_comparison();
+    fn parse_expression(&mut self) -> Result<Expr> {
+        self.parse_comma()
+    }
 
-        while self.match_any([Token::Ne, Token::EqEq]) {
-            let operator = self.previous().cloned().unwrap();
-            let rhs = self.parse_comparison();
+    /// `a, b, c` evaluates to its last operand. Places where a bare comma separates
+    /// things, like the arguments of a call, should parse each item with
+    /// `parse_assignment` so the comma is left for them; a comma expression there
+    /// needs parentheses.
+    fn parse_comma(&mut self) -> Result<Expr> {
+        self.binary([TokenKind::Comma], Self::parse_assignment)
+    }
 
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(rhs));
+    /// There are no variables or other assignable expressions yet, so a compound
+    /// assignment is always an error, reported at the operator.
+    fn parse_assignment(&mut self) -> Result<Expr> {
+        let expr = self.parse_ternary()?;
+
+        if let Some(operator) = self.match_any([
+            TokenKind::PlusEq,
+            TokenKind::MinusEq,
+            TokenKind::StarEq,
+            TokenKind::SlashEq,
+        ]) {
+            return Err(
+                self.error_at_previous(&format!("Invalid assignment target for '{operator}'"))
+            );
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_comparison(&mut self) -> Expr {
-        let mut expr = self.parse_term();
+    /// `a ? b : c ? d : e` is right-associative, nesting the second conditional in
+    /// the else branch.
+    fn parse_ternary(&mut self) -> Result<Expr> {
+        let condition = self.parse_equality()?;
 
-        while self.match_any([Token::Gt, Token::Ge, Token::Lt, Token::Le]) {
-            let operator = self.previous().cloned().unwrap();
-            let rhs = self.parse_term();
+        let Some(question) = self.match_any([TokenKind::Question]) else {
+            return Ok(condition);
+        };
 
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(rhs));
+        let then = self.nested(Self::parse_ternary)?;
+
+        if self.match_any([TokenKind::Colon]).is_none() {
+            return Err(self.error(&format!(
+                "Expected ':' to match the '?' on line {}",
+                question.line
+            )));
         }
 
-        expr
+        let otherwise = self.nested(Self::parse_ternary)?;
+
+        Ok(Expr::Ternary(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
     }
 
-    fn parse_term(&mut self) -> Expr {
-        let mut expr = self.parse_factor();
+    fn parse_equality(&mut self) -> Result<Expr> {
+        self.binary([TokenKind::Ne, TokenKind::EqEq], Self::parse_bitor)
+    }
 
-        while self.match_any([Token::Plus, Token::Minus]) {
-            let operator = self.previous().cloned().unwrap();
-            let rhs = self.parse_factor();
+    /// The bitwise operators sit between equality and comparison, so `a & b == c`
+    /// compares the result of `a & b`. Shifts bind tighter than comparison.
+    fn parse_bitor(&mut self) -> Result<Expr> {
+        self.binary([TokenKind::Pipe], Self::parse_bitxor)
+    }
 
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(rhs));
-        }
+    fn parse_bitxor(&mut self) -> Result<Expr> {
+        self.binary([TokenKind::Caret], Self::parse_bitand)
+    }
 
-        expr
+    fn parse_bitand(&mut self) -> Result<Expr> {
+        self.binary([TokenKind::Amp], Self::parse_comparison)
     }
 
-    fn parse_factor(&mut self) -> Expr {
-        let mut expr = self.parse_unary();
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        self.binary(
+            [TokenKind::Gt, TokenKind::Ge, TokenKind::Lt, TokenKind::Le],
+            Self::parse_shift,
+        )
+    }
 
-        while self.match_any([Token::Slash, Token::Star]) {
-            let operator = self.previous().cloned().unwrap();
-            let rhs = self.parse_unary();
+    fn parse_shift(&mut self) -> Result<Expr> {
+        self.binary([TokenKind::Shl, TokenKind::Shr], Self::parse_term)
+    }
 
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(rhs));
+    fn parse_term(&mut self) -> Result<Expr> {
+        self.binary([TokenKind::Plus, TokenKind::Minus], Self::parse_factor)
+    }
+
+    fn parse_factor(&mut self) -> Result<Expr> {
+        self.binary(
+            [TokenKind::Slash, TokenKind::Star, TokenKind::Percent],
+            Self::parse_unary,
+        )
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if let Some(operator) = self.match_any([TokenKind::Not, TokenKind::Minus]) {
+            let rhs = self.nested(Self::parse_unary)?;
+
+            return Ok(fold_unary(operator, rhs));
         }
 
-        expr
+        self.parse_postfix()
     }
 
-    fn parse_unary(&mut self) -> Expr {
-        if self.match_any([Token::Not, Token::Minus]) {
-            let operator = self.previous().cloned().unwrap();
-            let rhs = self.parse_unary();
+    /// Indexing binds tighter than any prefix operator, so `-xs[0]` negates the
+    /// element and `xs[0][1]` indexes the result of `xs[0]`.
+    fn parse_postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_primary()?;
+        let mut height = None;
+
+        while let Some(bracket) = self.match_any([TokenKind::LeftBracket]) {
+            let index = self.nested(Self::parse_expression)?;
 
-            return Expr::Unary(operator, Box::new(rhs));
+            if self.match_any([TokenKind::RightBracket]).is_none() {
+                return Err(self.error(&format!(
+                    "Expected ']' to close the '[' on line {}",
+                    bracket.line
+                )));
+            }
+
+            height = Some(self.link(height.unwrap_or_else(|| expr.height()), &index, &bracket)?);
+            expr = Expr::Index(Box::new(expr), bracket, Box::new(index));
         }
 
-        self.parse_primary()
+        Ok(expr)
     }
 
-    fn parse_primary(&mut self) -> Expr {
-        if self.match_any([Token::False, Token::True, Token::Nil])
-            || self.match_number()
-            || self.match_string()
+    fn parse_primary(&mut self) -> Result<Expr> {
+        if let Some(literal) = self
+            .match_any([TokenKind::False, TokenKind::True, TokenKind::Nil])
+            .or_else(|| self.match_number())
         {
-            return Expr::Literal(self.previous().cloned().unwrap());
+            return Ok(Expr::Literal(literal));
+        }
+
+        if let Some(literal) = self.match_string_start() {
+            return self.string_literal(literal);
+        }
+
+        if let Some(bracket) = self.match_any([TokenKind::LeftBracket]) {
+            return self.array_literal(bracket);
+        }
+
+        if let Some(brace) = self.match_any([TokenKind::LeftBrace]) {
+            return self.map_literal(brace);
+        }
+
+        if self.match_any([TokenKind::LeftParen]).is_some() {
+            let expr = self.nested(Self::parse_expression)?;
+
+            if self.match_any([TokenKind::RightParen]).is_none() {
+                return Err(self.error("Expected ')' after expression"));
+            }
+
+            return Ok(Expr::Grouping(Box::new(expr)));
+        }
+
+        match self.peek().map(|token| &token.kind) {
+            Some(TokenKind::String(_) | TokenKind::Interpolation(_))
+                if self
+                    .peek()
+                    .is_some_and(|token| token.lexeme.starts_with('}')) =>
+            {
+                Err(self.error("Expected expression before '}'"))
+            }
+            // Statements aren't parsed yet, so there is never an enclosing loop.
+            Some(kind @ (TokenKind::Break | TokenKind::Continue)) => {
+                Err(self.error(&format!("Can't use '{kind}' outside of a loop")))
+            }
+            Some(TokenKind::Ident(name)) => Err(self.error(&diagnostics::with_keyword_suggestion(
+                format!("Expected expression, found {}", diagnostics::quote(name)),
+                name,
+            ))),
+            Some(kind) if !self.is_at_end() => Err(self.error(&format!(
+                "Expected expression, found {}",
+                diagnostics::quote(&kind.to_string())
+            ))),
+            _ => Err(self.error("Expected expression, found end of input")),
         }
+    }
 
-        if self.match_any([Token::LeftParen]) {
-            let expr = self.parse_expression();
+    /// Adjacent string literals are concatenated at parse time, so `"a" "b"` is the
+    /// single literal `"ab"`. If any of them is interpolated the result is a single
+    /// interpolation, so `"a${x}" "b"` splices `x` between `"a"` and `"b"`.
+    fn string_literal(&mut self, first: Token) -> Result<Expr> {
+        let mut pieces = vec![first];
+        let mut exprs = vec![];
+
+        loop {
+            let last = pieces.len() - 1;
+
+            if matches!(pieces[last].kind, TokenKind::Interpolation(_)) {
+                exprs.push(self.nested(Self::parse_expression)?);
+
+                if let Some(piece) =
+                    self.match_piece(|kind| matches!(kind, TokenKind::Interpolation(_)), true)
+                {
+                    pieces.push(piece);
+                } else if let Some(piece) =
+                    self.match_piece(|kind| matches!(kind, TokenKind::String(_)), true)
+                {
+                    pieces.push(piece);
+                } else {
+                    return Err(self.error("Expected '}' after interpolated expression"));
+                }
+            } else if let Some(next) = self.match_string_start() {
+                merge_pieces(&mut pieces[last], next);
+            } else {
+                break;
+            }
+        }
+
+        if exprs.is_empty() {
+            Ok(Expr::Literal(pieces.swap_remove(0)))
+        } else {
+            Ok(Expr::Interpolation(pieces, exprs))
+        }
+    }
 
-            if !self.match_any([Token::RightParen]) {
-                panic!("Missing closing parenthesis");
+    /// Elements are separated by commas, with an optional trailing comma. Each one
+    /// is parsed below the comma operator so the commas are left as separators.
+    fn array_literal(&mut self, bracket: Token) -> Result<Expr> {
+        let mut elements = vec![];
+
+        while self.match_any([TokenKind::RightBracket]).is_none() {
+            if self.is_at_end() {
+                return Err(self.error(&format!(
+                    "Expected ']' to close the '[' on line {}",
+                    bracket.line
+                )));
             }
 
-            return Expr::Grouping(Box::new(expr));
+            elements.push(self.nested(Self::parse_assignment)?);
+
+            if self.match_any([TokenKind::Comma]).is_none()
+                && !self.is_at_end()
+                && !self
+                    .peek()
+                    .is_some_and(|token| token.kind == TokenKind::RightBracket)
+            {
+                return Err(self.error(&format!(
+                    "Expected ',' or ']' in the array starting on line {}",
+                    bracket.line
+                )));
+            }
         }
 
-        panic!("Failed to parse primary expression")
+        Ok(Expr::ArrayLiteral(elements))
     }
 
-    fn match_any(&mut self, iter: impl IntoIterator<Item = Token>) -> bool {
-        for token in iter {
-            if !self.is_at_end() && self.peek().is_some_and(|t| t == &token) {
-                self.advance();
-                return true;
+    /// A `{` can only start a map here, since there are no blocks in expression
+    /// position. Keys are string, number or identifier tokens, and using the same
+    /// key twice is an error rather than letting the last one win.
+    fn map_literal(&mut self, brace: Token) -> Result<Expr> {
+        let mut entries: Vec<(Token, Expr)> = vec![];
+
+        while self.match_any([TokenKind::RightBrace]).is_none() {
+            if self.is_at_end() {
+                return Err(self.error(&format!(
+                    "Expected '}}' to close the '{{' on line {}",
+                    brace.line
+                )));
+            }
+
+            let Some(key) = self.match_map_key() else {
+                return Err(self.error("Expected a string, number or identifier as a map key"));
+            };
+
+            if let Some((first, _)) = entries
+                .iter()
+                .find(|(other, _)| map_key(other) == map_key(&key))
+            {
+                return Err(self.error_at(
+                    Some(&key),
+                    &format!(
+                        "Duplicate map key {} (first used on line {})",
+                        diagnostics::quote(&key.lexeme),
+                        first.line
+                    ),
+                ));
+            }
+
+            if self.match_any([TokenKind::Colon]).is_none() {
+                return Err(self.error("Expected ':' after map key"));
+            }
+
+            entries.push((key, self.nested(Self::parse_assignment)?));
+
+            if self.match_any([TokenKind::Comma]).is_none()
+                && !self.is_at_end()
+                && !self
+                    .peek()
+                    .is_some_and(|token| token.kind == TokenKind::RightBrace)
+            {
+                return Err(self.error(&format!(
+                    "Expected ',' or '}}' in the map starting on line {}",
+                    brace.line
+                )));
             }
         }
 
-        false
+        Ok(Expr::MapLiteral(entries))
     }
 
-    fn match_string(&mut self) -> bool {
-        if matches!(self.peek(), Some(Token::String(_))) {
-            self.advance();
-            true
-        } else {
-            false
+    /// Parses a left-associative chain of `operand`s joined by any of `kinds`.
+    fn binary<const N: usize>(
+        &mut self,
+        kinds: [TokenKind; N],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut expr = operand(self)?;
+        let mut height = None;
+
+        while let Some(operator) = self.match_any(kinds.clone()) {
+            let rhs = operand(self)?;
+
+            height = Some(self.link(height.unwrap_or_else(|| expr.height()), &rhs, &operator)?);
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(rhs));
         }
+
+        Ok(expr)
     }
 
-    fn match_number(&mut self) -> bool {
-        if matches!(self.peek(), Some(Token::Number(_))) {
-            self.advance();
-            true
-        } else {
-            false
+    /// Each link of a chain like `a + b + c` or `xs[0][1]` wraps everything before
+    /// it in one more node, so the chain so far, `height` levels tall, and the new
+    /// operand `rhs` together must stay within the tree height limit. Returns the
+    /// height of the linked node. Callers only measure an operand once an operator
+    /// follows it, so expressions that aren't chained are never walked.
+    fn link(&self, height: usize, rhs: &Expr, operator: &Token) -> Result<usize> {
+        let height = 1 + height.max(rhs.height());
+
+        if height > MAX_TREE_DEPTH {
+            return Err(self.error_at(Some(operator), "Expression is nested too deeply"));
+        }
+
+        Ok(height)
+    }
+
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(self.error("Expression is nested too deeply"));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+
+        result
+    }
+
+    /// Consumes the next token if it matches, returning it.
+    fn match_token(&mut self, matches: impl Fn(&Token) -> bool) -> Option<Token> {
+        let token = self
+            .peek()
+            .filter(|token| !self.is_at_end() && matches(token))
+            .cloned()?;
+
+        self.advance();
+        Some(token)
+    }
+
+    fn match_any(&mut self, iter: impl IntoIterator<Item = TokenKind>) -> Option<Token> {
+        let kinds = iter.into_iter().collect::<Vec<_>>();
+        self.match_token(|token| kinds.contains(&token.kind))
+    }
+
+    fn match_string(&mut self) -> Option<Token> {
+        self.match_piece(|kind| matches!(kind, TokenKind::String(_)), false)
+    }
+
+    /// Matches a string literal or the first piece of an interpolated string.
+    fn match_string_start(&mut self) -> Option<Token> {
+        self.match_string()
+            .or_else(|| self.match_piece(|kind| matches!(kind, TokenKind::Interpolation(_)), false))
+    }
+
+    /// Matches a piece of an interpolated string. Pieces that follow an expression
+    /// start at its closing `}`, which tells them apart from string literals.
+    fn match_piece(
+        &mut self,
+        is_kind: impl Fn(&TokenKind) -> bool,
+        continued: bool,
+    ) -> Option<Token> {
+        self.match_token(|token| is_kind(&token.kind) && token.lexeme.starts_with('}') == continued)
+    }
+
+    fn match_map_key(&mut self) -> Option<Token> {
+        self.match_string()
+            .or_else(|| self.match_number())
+            .or_else(|| self.match_token(|token| matches!(token.kind, TokenKind::Ident(_))))
+    }
+
+    fn match_number(&mut self) -> Option<Token> {
+        self.match_token(|token| matches!(token.kind, TokenKind::Number(_)))
+    }
+
+    fn error(&self, message: &str) -> Error {
+        self.error_at(self.peek().or(self.tokens.last()), message)
+    }
+
+    fn error_at_previous(&self, message: &str) -> Error {
+        self.error_at(self.previous(), message)
+    }
+
+    fn error_at(&self, token: Option<&Token>, message: &str) -> Error {
+        match token {
+            Some(token) => Error::parse(token, message),
+            None => Error::parse(
+                &Token::new(TokenKind::Eof, "", Span::default(), 1, 1),
+                message,
+            ),
         }
     }
 
@@ -152,27 +496,483 @@ impl Parser {
     fn is_at_end(&self) -> bool {
         self.tokens
             .get(self.current)
-            .map_or(true, |token| matches!(token, Token::Eof))
+            .is_none_or(|token| matches!(token.kind, TokenKind::Eof))
+    }
+}
+
+/// Appends the text of `next` to a closed string piece, which takes on the kind
+/// of `next` so an interpolation that follows stays open. Its span grows to cover
+/// `next`, and that is what errors point at.
+///
+/// The piece keeps the lexeme of its first part, so unlike a scanned token its
+/// lexeme is no longer the source text of its span. The parser only has tokens,
+/// not the comments and whitespace between them, so it can't spell the merged
+/// literal as written; slice the source by the span for that. Nothing reads a
+/// string's lexeme once it's parsed: the printers use the joined text in the kind.
+fn merge_pieces(piece: &mut Token, next: Token) {
+    let text = |kind: &TokenKind| match kind {
+        TokenKind::String(s) | TokenKind::Interpolation(s) => s.clone(),
+        _ => String::new(),
+    };
+    let merged = text(&piece.kind) + &text(&next.kind);
+
+    piece.kind = match next.kind {
+        TokenKind::Interpolation(_) => TokenKind::Interpolation(merged),
+        _ => TokenKind::String(merged),
+    };
+    piece.span = piece.span.to(next.span);
+}
+
+/// The key a map entry is stored under, with identifiers standing for their name.
+fn map_key(key: &Token) -> TokenKind {
+    match &key.kind {
+        TokenKind::Ident(name) => TokenKind::String(name.clone()),
+        kind => kind.clone(),
+    }
+}
+
+/// Normalizes unary operators applied directly to literals: `-` on a number literal
+/// becomes a negative literal, and `!!` on a boolean literal is dropped. Anything
+/// else, including `-` on a string or on a parenthesized literal, is left as is.
+fn fold_unary(operator: Token, rhs: Expr) -> Expr {
+    match (&operator.kind, rhs) {
+        (
+            TokenKind::Minus,
+            Expr::Literal(Token {
+                kind: TokenKind::Number(n),
+                lexeme,
+                span,
+                ..
+            }),
+        ) => {
+            let digits = lexeme.trim_start_matches('-');
+            let lexeme = if (-n).is_sign_negative() {
+                format!("-{digits}")
+            } else {
+                digits.to_string()
+            };
+
+            Expr::Literal(Token::new(
+                TokenKind::Number(-n),
+                lexeme,
+                operator.span.to(span),
+                operator.line,
+                operator.column,
+            ))
+        }
+        (TokenKind::Not, Expr::Unary(inner, literal))
+            if inner.kind == TokenKind::Not
+                && matches!(
+                    *literal,
+                    Expr::Literal(Token {
+                        kind: TokenKind::True | TokenKind::False,
+                        ..
+                    })
+                ) =>
+        {
+            *literal
+        }
+        (_, rhs) => Expr::Unary(operator, Box::new(rhs)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::Visitor;
+    use crate::print::PrettyPrinter;
+    use crate::scanner::Scanner;
     use crate::scanner::Token;
+    use crate::scanner::TokenKind;
+
+    fn from_kinds(kinds: impl IntoIterator<Item = TokenKind>) -> Vec<Token> {
+        kinds
+            .into_iter()
+            .enumerate()
+            .map(|(i, kind)| {
+                let lexeme = kind.to_string();
+                Token::new(kind, lexeme, Span::default(), 1, i + 1)
+            })
+            .collect()
+    }
+
+    /// Parses `source` and pretty-prints the result.
+    fn parse(source: &str) -> Result<String> {
+        let tokens = Scanner::new(source.to_string()).read_tokens().unwrap();
+        Parser::new(tokens)
+            .parse()
+            .map(|expr| PrettyPrinter.visit_expr(&expr))
+    }
 
     #[test]
     fn test_parse() {
-        let tokens = vec![
-            Token::Number(6.0),
-            Token::Slash,
-            Token::Number(3.0),
-            Token::Minus,
-            Token::Number(1.0),
-            Token::Eof,
-        ];
-
-        let expr = Parser::new(tokens).parse();
+        let tokens = from_kinds([
+            TokenKind::Number(6.0),
+            TokenKind::Slash,
+            TokenKind::Number(3.0),
+            TokenKind::Minus,
+            TokenKind::Number(1.0),
+            TokenKind::Eof,
+        ]);
+
+        let expr = Parser::new(tokens).parse().unwrap();
         println!("{expr:?}");
     }
+
+    #[test]
+    fn test_parse_skips_trivia() {
+        let tokens = Scanner::new("1 /* one */ + // two\n2".to_string())
+            .with_trivia(true)
+            .read_tokens()
+            .unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+
+        assert_eq!(PrettyPrinter.visit_expr(&expr), "(+ 1 2)");
+    }
+
+    #[test]
+    fn test_parse_leading_equals() {
+        let tokens = from_kinds([TokenKind::Eq, TokenKind::Number(1.0), TokenKind::Eof]);
+        assert_eq!(
+            Parser::new(tokens).parse().unwrap_err().to_string(),
+            "Error parsing expression at 1:1: Unexpected '=' — assignment requires a variable name on the left"
+        );
+
+        let tokens = from_kinds([TokenKind::EqEq, TokenKind::Number(1.0), TokenKind::Eof]);
+        assert_eq!(
+            Parser::new(tokens).parse().unwrap_err().to_string(),
+            "Error parsing expression at 1:1: Unexpected '==' at start of statement"
+        );
+    }
+
+    #[test]
+    fn test_parse_keyword_suggestion() {
+        let tokens = from_kinds([TokenKind::Ident("null".to_string()), TokenKind::Eof]);
+        assert_eq!(
+            Parser::new(tokens).parse().unwrap_err().to_string(),
+            "Error parsing expression at 1:1: Expected expression, found 'null' (did you mean 'nil'?)"
+        );
+
+        let tokens = from_kinds([TokenKind::Ident("iff".to_string()), TokenKind::Eof]);
+        assert_eq!(
+            Parser::new(tokens).parse().unwrap_err().to_string(),
+            "Error parsing expression at 1:1: Expected expression, found 'iff'"
+        );
+    }
+
+    #[test]
+    fn test_parse_long_token_truncated() {
+        let name = "a".repeat(10_000);
+        let tokens = from_kinds([
+            TokenKind::Number(1.0),
+            TokenKind::Ident(name),
+            TokenKind::Eof,
+        ]);
+        let message = Parser::new(tokens).parse().unwrap_err().to_string();
+
+        assert!(message.len() < 150, "{message}");
+        assert!(message.ends_with("…' (truncated, 10000 chars) after expression"));
+    }
+
+    #[test]
+    fn test_parse_long_chains() {
+        // Chains that each fit the limit, but not once they're operands of each
+        // other.
+        let nested_chains = |link: &str| {
+            (0..5).fold("[1]".to_string(), |inner, _| {
+                format!("({inner}){}", link.repeat(900))
+            })
+        };
+
+        // Parsing, printing and dropping all run on the main thread's stack, which
+        // is larger than a test thread's.
+        std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || {
+                // Every kind of chain is rejected rather than building a tree too deep
+                // to print or drop.
+                for source in [
+                    "1".to_string() + &" + 1".repeat(10_000),
+                    "1".to_string() + &", 1".repeat(10_000),
+                    "[1]".to_string() + &"[0]".repeat(10_000),
+                    nested_chains(" + 1"),
+                    nested_chains(", 1"),
+                    nested_chains("[0]"),
+                ] {
+                    let message = parse(&source).unwrap_err().to_string();
+                    assert!(
+                        message.ends_with("Expression is nested too deeply"),
+                        "{message}"
+                    );
+                }
+
+                let printed = parse(&("1".to_string() + &" + 1".repeat(1_000))).unwrap();
+                assert!(printed.starts_with(&"(+ ".repeat(1_000)));
+
+                // Groups around a chain add one level each, bounded by the nesting limit.
+                let source = "(".repeat(100) + "1" + &" * 1".repeat(1_000) + &")".repeat(100);
+                assert!(parse(&source).is_ok());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_parse_adjacent_strings() {
+        let source = "\"one, \"\n// comment\n\"two, \"  \"three\"".to_string();
+        let tokens = Scanner::new(source.clone()).read_tokens().unwrap();
+
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            PrettyPrinter.visit_expr(&expr),
+            "\"one, two, three\"".to_string()
+        );
+        let Expr::Literal(literal) = expr else {
+            panic!("adjacent strings should merge into one literal");
+        };
+        // The text is joined, the span covers every part, and the lexeme is still
+        // the first part's, since the source between the parts isn't known.
+        assert_eq!(
+            literal.kind,
+            TokenKind::String("one, two, three".to_string())
+        );
+        assert_eq!(literal.span, Span::new(0, source.len()));
+        assert_eq!(literal.lexeme, "\"one, \"");
+
+        let tokens = Scanner::new("\"a\" name".to_string())
+            .read_tokens()
+            .unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_fold_unary() {
+        let parse_expr = |source: &str| {
+            let tokens = Scanner::new(source.to_string()).read_tokens().unwrap();
+            Parser::new(tokens).parse().unwrap()
+        };
+
+        assert_eq!(
+            parse_expr("-123"),
+            Expr::Literal(Token::new(
+                TokenKind::Number(-123.0),
+                "-123",
+                Span::new(0, 4),
+                1,
+                1
+            ))
+        );
+        assert_eq!(
+            parse_expr("--5"),
+            Expr::Literal(Token::new(
+                TokenKind::Number(5.0),
+                "5",
+                Span::new(0, 3),
+                1,
+                1
+            ))
+        );
+        for (source, lexeme) in [("-0", "-0"), ("-0.0", "-0.0"), ("--0", "0")] {
+            let Expr::Literal(token) = parse_expr(source) else {
+                panic!("{source} should fold to a literal");
+            };
+            let TokenKind::Number(n) = token.kind else {
+                panic!("{source} should fold to a number");
+            };
+
+            assert_eq!(token.lexeme, lexeme);
+            assert_eq!(n.is_sign_negative(), lexeme.starts_with('-'), "{source}");
+        }
+        assert_eq!(
+            parse_expr("!!true"),
+            Expr::Literal(Token::new(TokenKind::True, "true", Span::new(2, 6), 1, 3))
+        );
+        assert_eq!(parse("!!!false").unwrap(), "(! false)");
+        assert_eq!(parse("-(-(-3))").unwrap(), "(- (group (- (group -3))))");
+        assert_eq!(
+            parse("-\"s\" + !!nil").unwrap(),
+            "(+ (- \"s\") (! (! nil)))"
+        );
+    }
+
+    #[test]
+    fn test_parse_modulo() {
+        assert_eq!(parse("10 % 3 % 2").unwrap(), "(% (% 10 3) 2)");
+        assert_eq!(parse("1 + 6 % 4 * 2").unwrap(), "(+ 1 (* (% 6 4) 2))");
+        // Unary minus binds tighter than `%`, so this is (-7) % 2.
+        assert_eq!(parse("-7 % 2").unwrap(), "(% -7 2)");
+        assert_eq!(parse("-(7 % 2)").unwrap(), "(- (group (% 7 2)))");
+    }
+
+    #[test]
+    fn test_parse_bitwise() {
+        assert_eq!(parse("1 | 2 & 3 << 1").unwrap(), "(| 1 (& 2 (<< 3 1)))");
+        assert_eq!(
+            parse("1 ^ 2 | 3 ^ 4 & 5").unwrap(),
+            "(| (^ 1 2) (^ 3 (& 4 5)))"
+        );
+        assert_eq!(parse("8 >> 1 >= 2 + 1").unwrap(), "(>= (>> 8 1) (+ 2 1))");
+        assert_eq!(parse("6 & 3 == 2 < 1").unwrap(), "(== (& 6 3) (< 2 1))");
+    }
+
+    #[test]
+    fn test_parse_compound_assignment() {
+        assert_eq!(
+            parse("1 += 2").unwrap_err().to_string(),
+            "Error parsing expression at 1:3: Invalid assignment target for '+='"
+        );
+        assert_eq!(
+            parse("(1) /= 2").unwrap_err().to_string(),
+            "Error parsing expression at 1:5: Invalid assignment target for '/='"
+        );
+        assert_eq!(
+            parse("(1 + 2 *= 3)").unwrap_err().to_string(),
+            "Error parsing expression at 1:8: Invalid assignment target for '*='"
+        );
+    }
+
+    #[test]
+    fn test_parse_ternary() {
+        assert_eq!(parse("true ? 1 : 2").unwrap(), "(?: true 1 2)");
+        assert_eq!(
+            parse("true ? 1 : false ? 2 : 3").unwrap(),
+            "(?: true 1 (?: false 2 3))"
+        );
+        assert_eq!(
+            parse("true ? false ? 1 : 2 : 3").unwrap(),
+            "(?: true (?: false 1 2) 3)"
+        );
+        assert_eq!(
+            parse("1 == 2 ? 3 : 4 + 5").unwrap(),
+            "(?: (== 1 2) 3 (+ 4 5))"
+        );
+        assert_eq!(
+            parse("(true ? 1 : 2) == 1").unwrap(),
+            "(== (group (?: true 1 2)) 1)"
+        );
+        assert_eq!(
+            parse("true ?\n1 + 2").unwrap_err().to_string(),
+            "Error parsing expression at 2:6: Expected ':' to match the '?' on line 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_comma() {
+        assert_eq!(parse("1, 2 + 3, 4").unwrap(), "(, (, 1 (+ 2 3)) 4)");
+        assert_eq!(parse("true ? 1 : 2, 3").unwrap(), "(, (?: true 1 2) 3)");
+        assert_eq!(parse("(1, 2) == 2").unwrap(), "(== (group (, 1 2)) 2)");
+        assert!(parse("1, ").is_err());
+    }
+
+    #[test]
+    fn test_parse_break_outside_loop() {
+        assert_eq!(
+            parse("\n  break").unwrap_err().to_string(),
+            "Error parsing expression at 2:3: Can't use 'break' outside of a loop"
+        );
+        assert_eq!(
+            parse("1 + continue").unwrap_err().to_string(),
+            "Error parsing expression at 1:5: Can't use 'continue' outside of a loop"
+        );
+        assert_eq!(
+            parse("Break").unwrap_err().to_string(),
+            "Error parsing expression at 1:1: Expected expression, found 'Break' (did you mean 'break'?)"
+        );
+    }
+
+    #[test]
+    fn test_parse_arrays() {
+        assert_eq!(parse("[]").unwrap(), "(array)");
+        assert_eq!(
+            parse("[1, [2, 3,], (4, 5)]").unwrap(),
+            "(array 1 (array 2 3) (group (, 4 5)))"
+        );
+        assert_eq!(
+            parse("[[1], [2]][0][1 + 0]").unwrap(),
+            "(index (index (array (array 1) (array 2)) 0) (+ 1 0))"
+        );
+        assert_eq!(
+            parse("-[1][0] * \"ab\"[1]").unwrap(),
+            "(* (- (index (array 1) 0)) (index \"ab\" 1))"
+        );
+        assert_eq!(
+            parse("[1,\n2").unwrap_err().to_string(),
+            "Error parsing expression at 2:2: Expected ']' to close the '[' on line 1"
+        );
+        assert_eq!(
+            parse("[1 2]").unwrap_err().to_string(),
+            "Error parsing expression at 1:4: Expected ',' or ']' in the array starting on line 1"
+        );
+        assert_eq!(
+            parse("[1][0\n").unwrap_err().to_string(),
+            "Error parsing expression at 1:6: Expected ']' to close the '[' on line 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_maps() {
+        assert_eq!(parse("{}").unwrap(), "(map)");
+        assert_eq!(
+            parse(r#"{ "key": 1 + 2, other: 2, 3: "three", }"#).unwrap(),
+            r#"(map ("key" (+ 1 2)) ("other" 2) (3 "three"))"#
+        );
+        assert_eq!(
+            parse("{ a: { b: {} }, c: [1, 2] }[\"a\"]").unwrap(),
+            r#"(index (map ("a" (map ("b" (map)))) ("c" (array 1 2))) "a")"#
+        );
+        assert_eq!(
+            parse("{ a: 1, b: 2, 1: 3, \"1\": 4 }").unwrap(),
+            r#"(map ("a" 1) ("b" 2) (1 3) ("1" 4))"#
+        );
+        assert_eq!(
+            parse("{ a: 1,\n\"a\": 2 }").unwrap_err().to_string(),
+            "Error parsing expression at 2:1: Duplicate map key '\"a\"' (first used on line 1)"
+        );
+        assert_eq!(
+            parse("{ true: 1 }").unwrap_err().to_string(),
+            "Error parsing expression at 1:3: Expected a string, number or identifier as a map key"
+        );
+        assert_eq!(
+            parse("{ a 1 }").unwrap_err().to_string(),
+            "Error parsing expression at 1:5: Expected ':' after map key"
+        );
+        assert_eq!(
+            parse("{ a: 1 b: 2 }").unwrap_err().to_string(),
+            "Error parsing expression at 1:8: Expected ',' or '}' in the map starting on line 1"
+        );
+        assert_eq!(
+            parse("{ a: 1,\n").unwrap_err().to_string(),
+            "Error parsing expression at 1:8: Expected '}' to close the '{' on line 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_interpolation() {
+        assert_eq!(
+            parse("\"hello, ${nil}!\"").unwrap(),
+            "(interpolate \"hello, \" nil \"!\")"
+        );
+        assert_eq!(
+            parse("\"${1 + 2}\" + \"${true} and ${\"b${-3}\"}\"").unwrap(),
+            "(+ (interpolate (+ 1 2)) (interpolate true \" and \" (interpolate \"b\" -3)))"
+        );
+        assert_eq!(
+            parse("\"x${1}\" \"y\"").unwrap(),
+            "(interpolate \"x\" 1 \"y\")"
+        );
+        assert_eq!(parse("\"a\" \"${2}\"").unwrap(), "(interpolate \"a\" 2)");
+        assert_eq!(
+            parse("\"a${1}b\" \"c\" \"${2}d\" \"e\" + \"f\"").unwrap(),
+            "(+ (interpolate \"a\" 1 \"bc\" 2 \"de\") \"f\")"
+        );
+        assert_eq!(
+            parse("\"${}\"").unwrap_err().to_string(),
+            "Error parsing expression at 1:4: Expected expression before '}'"
+        );
+        assert_eq!(
+            parse("\"${1 2}\"").unwrap_err().to_string(),
+            "Error parsing expression at 1:6: Expected '}' after interpolated expression"
+        );
+    }
 }
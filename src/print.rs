@@ -1,11 +1,23 @@
 use crate::ast::Expr;
 use crate::ast::Visitor;
+use crate::scanner::Token;
+use crate::scanner::TokenKind;
 
 pub struct PrettyPrinter;
 
+/// Prints an expression back as Lox source, adding parentheses only where
+/// precedence requires them. Parsing the output yields the same tree.
+pub struct SourcePrinter;
+
 impl Visitor<String> for PrettyPrinter {
     fn visit_expr(&self, expr: &Expr) -> String {
         match expr {
+            // Numbers are shown as written, so `1.0` and `1e0` aren't both `1`.
+            Expr::Literal(Token {
+                kind: TokenKind::Number(_),
+                lexeme,
+                ..
+            }) => lexeme.clone(),
             Expr::Literal(token) => token.to_string(),
             Expr::Unary(token, expr) => parenthesize([token.to_string(), self.visit_expr(expr)]),
             Expr::Binary(lhs, operator, rhs) => parenthesize([
@@ -14,10 +26,202 @@ impl Visitor<String> for PrettyPrinter {
                 self.visit_expr(rhs),
             ]),
             Expr::Grouping(expr) => parenthesize(["group".to_string(), self.visit_expr(expr)]),
+            Expr::ArrayLiteral(elements) => parenthesize(
+                std::iter::once("array".to_string())
+                    .chain(elements.iter().map(|element| self.visit_expr(element))),
+            ),
+            Expr::MapLiteral(entries) => parenthesize(
+                std::iter::once("map".to_string()).chain(
+                    entries
+                        .iter()
+                        .map(|(key, value)| parenthesize([map_key(key), self.visit_expr(value)])),
+                ),
+            ),
+            Expr::Index(target, _, index) => parenthesize([
+                "index".to_string(),
+                self.visit_expr(target),
+                self.visit_expr(index),
+            ]),
+            Expr::Ternary(condition, then, otherwise) => parenthesize([
+                "?:".to_string(),
+                self.visit_expr(condition),
+                self.visit_expr(then),
+                self.visit_expr(otherwise),
+            ]),
+            Expr::Interpolation(pieces, exprs) => {
+                let mut list = vec!["interpolate".to_string()];
+
+                for (i, piece) in pieces.iter().enumerate() {
+                    let text = piece_text(piece);
+
+                    if !text.is_empty() {
+                        list.push(format!("\"{text}\""));
+                    }
+
+                    if let Some(expr) = exprs.get(i) {
+                        list.push(self.visit_expr(expr));
+                    }
+                }
+
+                parenthesize(list)
+            }
         }
     }
 }
 
+impl Visitor<String> for SourcePrinter {
+    fn visit_expr(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Literal(token) => match &token.kind {
+                TokenKind::String(s) => format!("\"{}\"", escape(s)),
+                TokenKind::Number(_) => token.lexeme.clone(),
+                kind => kind.to_string(),
+            },
+            Expr::Unary(token, rhs) => {
+                format!("{token}{}", self.operand(rhs, precedence(expr), false))
+            }
+            Expr::Binary(lhs, operator, rhs) if operator.kind == TokenKind::Comma => format!(
+                "{}, {}",
+                self.operand(lhs, precedence(expr), false),
+                self.operand(rhs, precedence(expr), true),
+            ),
+            Expr::Binary(lhs, operator, rhs) => format!(
+                "{} {operator} {}",
+                self.operand(lhs, precedence(expr), false),
+                self.operand(rhs, precedence(expr), true),
+            ),
+            Expr::Grouping(expr) => format!("({})", self.visit_expr(expr)),
+            // Elements that are comma expressions need parentheses to stay whole.
+            Expr::ArrayLiteral(elements) => format!(
+                "[{}]",
+                elements
+                    .iter()
+                    .map(|element| self.operand(element, COMMA_PRECEDENCE + 1, false))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expr::MapLiteral(entries) if entries.is_empty() => "{}".to_string(),
+            Expr::MapLiteral(entries) => format!(
+                "{{ {} }}",
+                entries
+                    .iter()
+                    .map(|(key, value)| format!(
+                        "{}: {}",
+                        match &key.kind {
+                            TokenKind::String(s) => format!("\"{}\"", escape(s)),
+                            kind => kind.to_string(),
+                        },
+                        self.operand(value, COMMA_PRECEDENCE + 1, false)
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expr::Index(target, _, index) => format!(
+                "{}[{}]",
+                self.operand(target, precedence(expr), false),
+                self.visit_expr(index)
+            ),
+            // The condition is parenthesized if it's a conditional itself, the else
+            // branch isn't since `?:` is right-associative.
+            Expr::Ternary(condition, then, otherwise) => format!(
+                "{} ? {} : {}",
+                self.operand(condition, precedence(expr), true),
+                self.operand(then, precedence(expr), false),
+                self.operand(otherwise, precedence(expr), false),
+            ),
+            Expr::Interpolation(pieces, exprs) => {
+                let mut s = "\"".to_string();
+
+                for (i, piece) in pieces.iter().enumerate() {
+                    s.push_str(&escape(piece_text(piece)));
+
+                    if let Some(expr) = exprs.get(i) {
+                        s.push_str(&format!("${{{}}}", self.visit_expr(expr)));
+                    }
+                }
+
+                s.push('"');
+                s
+            }
+        }
+    }
+}
+
+impl SourcePrinter {
+    /// Binary operators are left-associative, so a right operand of equal precedence
+    /// needs parentheses to keep its grouping.
+    fn operand(&self, expr: &Expr, parent: u8, is_rhs: bool) -> String {
+        let s = self.visit_expr(expr);
+
+        if precedence(expr) < parent || (is_rhs && precedence(expr) == parent) {
+            format!("({s})")
+        } else {
+            s
+        }
+    }
+}
+
+/// Identifier keys are shown as the strings they stand for.
+fn map_key(key: &Token) -> String {
+    match &key.kind {
+        TokenKind::Ident(name) => format!("\"{name}\""),
+        kind => kind.to_string(),
+    }
+}
+
+fn piece_text(piece: &Token) -> &str {
+    match &piece.kind {
+        TokenKind::String(s) | TokenKind::Interpolation(s) => s,
+        _ => "",
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '$' if chars.peek() == Some(&'{') => escaped.push_str("\\$"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+const COMMA_PRECEDENCE: u8 = 0;
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Ternary(..) => 1,
+        Expr::Binary(_, operator, _) => match operator.kind {
+            TokenKind::Comma => COMMA_PRECEDENCE,
+            TokenKind::EqEq | TokenKind::Ne => 2,
+            TokenKind::Pipe => 3,
+            TokenKind::Caret => 4,
+            TokenKind::Amp => 5,
+            TokenKind::Gt | TokenKind::Ge | TokenKind::Lt | TokenKind::Le => 6,
+            TokenKind::Shl | TokenKind::Shr => 7,
+            TokenKind::Plus | TokenKind::Minus => 8,
+            _ => 9,
+        },
+        Expr::Unary(..) => 10,
+        Expr::Index(..) => 11,
+        Expr::Literal(_)
+        | Expr::Grouping(_)
+        | Expr::Interpolation(..)
+        | Expr::ArrayLiteral(_)
+        | Expr::MapLiteral(_) => 12,
+    }
+}
+
 fn parenthesize(list: impl IntoIterator<Item = String>) -> String {
     let mut iter = list.into_iter();
 
@@ -40,25 +244,102 @@ fn parenthesize(list: impl IntoIterator<Item = String>) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::scanner::Span;
     use crate::scanner::Token;
+    use crate::scanner::TokenKind;
+
+    fn token(kind: TokenKind) -> Token {
+        let lexeme = kind.to_string();
+        Token::new(kind, lexeme, Span::default(), 1, 1)
+    }
 
     #[test]
     fn test_pretty_print() {
         let expr = Expr::Binary(
             Box::new(Expr::Unary(
-                Token::Minus,
-                Box::new(Expr::Literal(Token::Number(123.0))),
+                token(TokenKind::Minus),
+                Box::new(Expr::Literal(Token::new(
+                    TokenKind::Number(123.0),
+                    "123.0",
+                    Span::default(),
+                    1,
+                    1,
+                ))),
             )),
-            Token::Star,
-            Box::new(Expr::Grouping(Box::new(Expr::Literal(Token::Number(
-                45.67,
+            token(TokenKind::Star),
+            Box::new(Expr::Grouping(Box::new(Expr::Literal(token(
+                TokenKind::Number(45.67),
             ))))),
         );
 
         let printer = PrettyPrinter;
         assert_eq!(
             printer.visit_expr(&expr),
-            format!("(* (- 123) (group 45.67))")
+            format!("(* (- 123.0) (group 45.67))")
+        );
+    }
+
+    #[test]
+    fn test_source_print_round_trip() {
+        let sources = [
+            "1 + 2 * 3",
+            "(1 + 2) * 3",
+            "1 - (2 - 3)",
+            "-(-1) == !true",
+            "\"a\" + \"b\" >= nil",
+            r#""say \"hi\"\n\t\\" == "✓""#,
+            "((1))",
+            "10 % 3 % 2 + 1 % (2 % 3)",
+            "1 | 2 ^ 3 & 4 < 1 << 2 + 1 == (1 | 2) & (3 >> 1)",
+            "(true ? 1 : 2) ? 1 == 2 ? 3 : 4 : nil ? 5 : -(6 ? 7 : 8)",
+            "1, 2 + 3, (4, 5), true ? (6, 7) : 8",
+            "[1, (2, 3), [true ? 4 : 5], []][0][1] + (-[6])[0]",
+            r#"{ "a\n": (1, 2), b: { 3: {} }, 4.5: [5] }["b"] == {}"#,
+            r#""hello, ${nil}!" + "${1 + 2} is ${"three ${-3}"}" + "\${literal} $5""#,
+        ];
+
+        for source in sources {
+            let tokens = Scanner::new(source.to_string()).read_tokens().unwrap();
+            let expr = Parser::new(tokens).parse().unwrap();
+            let emitted = SourcePrinter.visit_expr(&expr);
+            assert_eq!(emitted, source);
+
+            let tokens = Scanner::new(emitted).read_tokens().unwrap();
+            let reparsed = Parser::new(tokens).parse().unwrap();
+            assert_eq!(
+                PrettyPrinter.visit_expr(&reparsed),
+                PrettyPrinter.visit_expr(&expr)
+            );
+        }
+    }
+
+    #[test]
+    fn test_print_number_spellings() {
+        for source in [
+            "0.5", "1e3", "100", "1.0", "1_000", "2.5E-3", "-0.50", "-0", "-0.0",
+        ] {
+            let tokens = Scanner::new(source.to_string()).read_tokens().unwrap();
+            let expr = Parser::new(tokens).parse().unwrap();
+
+            assert_eq!(PrettyPrinter.visit_expr(&expr), source);
+            assert_eq!(SourcePrinter.visit_expr(&expr), source);
+        }
+    }
+
+    #[test]
+    fn test_source_print_adds_needed_parentheses() {
+        let expr = Expr::Binary(
+            Box::new(Expr::Literal(token(TokenKind::Number(1.0)))),
+            token(TokenKind::Minus),
+            Box::new(Expr::Binary(
+                Box::new(Expr::Literal(token(TokenKind::Number(2.0)))),
+                token(TokenKind::Plus),
+                Box::new(Expr::Literal(token(TokenKind::Number(3.0)))),
+            )),
         );
+
+        assert_eq!(SourcePrinter.visit_expr(&expr), "1 - (2 + 3)");
     }
 }
@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::scanner::Scanner;
+use crate::Error;
+use crate::Result;
+use crate::FORMAT_VERSION;
+use crate::VERSION_HEADER;
+
+/// Name of the cache file written inside the scanned directory.
+pub const CACHE_FILE: &str = "tokens.scan-cache";
+
+const FILE_HEADER: &str = "# file: ";
+
+/// Normalized token dumps keyed by path relative to the scanned directory.
+pub type Dumps = BTreeMap<String, Vec<String>>;
+
+/// Scans every `.lox` file under `dir` and compares the token streams against the
+/// cache, writing the cache instead if it doesn't exist yet or `update` is set.
+/// Returns a report of the files whose token streams changed.
+pub fn run(dir: &Path, ignore_positions: bool, update: bool) -> Result<Vec<String>> {
+    let cache_path = dir.join(CACHE_FILE);
+    let dumps = scan_dir(dir)?;
+
+    let cached = match fs::read_to_string(&cache_path) {
+        Ok(cache) if !update => Some(parse_cache(&cache)?),
+        Ok(_) => None,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    match cached {
+        Some(cached) => Ok(diff(&cached, &dumps, ignore_positions)),
+        None => {
+            fs::write(&cache_path, write_cache(&dumps))?;
+            Ok(vec![])
+        }
+    }
+}
+
+pub fn scan_dir(dir: &Path) -> Result<Dumps> {
+    let mut dumps = Dumps::new();
+    visit(dir, dir, &mut dumps)?;
+    Ok(dumps)
+}
+
+fn visit(root: &Path, dir: &Path, dumps: &mut Dumps) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            visit(root, &path, dumps)?;
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            let name = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let reader = BufReader::new(File::open(&path)?);
+
+            dumps.insert(name, dump(Scanner::from_reader(reader)));
+        }
+    }
+
+    Ok(())
+}
+
+/// One line per token: `line<TAB>kind<TAB>lexeme`, followed by a
+/// `line<TAB>error<TAB>message` line for each scan error. Every line starts with
+/// its position, so [`diff`] can drop it from both.
+pub fn dump(mut scanner: Scanner) -> Vec<String> {
+    let result = scanner.scan_all();
+    let tokens = result
+        .tokens
+        .iter()
+        .map(|token| format!("{}\t{:?}\t{:?}", token.line, token.kind, token.lexeme));
+    let errors = result.errors.iter().map(|err| match err {
+        Error::InterpretErrorWithIndex {
+            line_index,
+            message,
+            ..
+        } => format!("{line_index}\terror\t{message}"),
+        err => format!("-\terror\t{err}"),
+    });
+
+    tokens.chain(errors).collect()
+}
+
+pub fn write_cache(dumps: &Dumps) -> String {
+    let mut cache = format!("{VERSION_HEADER}{FORMAT_VERSION}\n");
+
+    for (name, lines) in dumps {
+        cache.push_str(FILE_HEADER);
+        cache.push_str(name);
+        cache.push('\n');
+
+        for line in lines {
+            cache.push_str(line);
+            cache.push('\n');
+        }
+    }
+
+    cache
+}
+
+/// Reads a cache written by [`write_cache`]. A cache from another format version or
+/// one that is otherwise malformed is an error, so a damaged baseline is never
+/// silently rewritten; `--update` replaces it without reading it.
+pub fn parse_cache(cache: &str) -> Result<Dumps> {
+    let mut lines = cache.lines();
+    let found = lines
+        .next()
+        .and_then(|line| line.strip_prefix(VERSION_HEADER))
+        .and_then(|version| version.parse().ok());
+
+    if found != Some(FORMAT_VERSION) {
+        return Err(Error::FormatVersion {
+            artifact: "The scan-diff cache",
+            found,
+        });
+    }
+
+    let mut dumps = Dumps::new();
+    let mut current = None;
+
+    for (i, line) in lines.enumerate() {
+        if let Some(name) = line.strip_prefix(FILE_HEADER) {
+            current = Some(dumps.entry(name.to_string()).or_default());
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line.to_string());
+        } else {
+            return Err(Error::Malformed {
+                artifact: "The scan-diff cache",
+                line: i + 2,
+            });
+        }
+    }
+
+    Ok(dumps)
+}
+
+/// Compares two sets of dumps, optionally ignoring line numbers so that changes
+/// which only move tokens around are not reported.
+pub fn diff(old: &Dumps, new: &Dumps, ignore_positions: bool) -> Vec<String> {
+    let normalize = |lines: &[String]| -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| match line.split_once('\t') {
+                Some((_, rest)) if ignore_positions => rest.to_string(),
+                _ => line.clone(),
+            })
+            .collect()
+    };
+
+    let mut report = vec![];
+
+    for name in old.keys().filter(|name| !new.contains_key(*name)) {
+        report.push(format!("{name}: removed"));
+    }
+
+    for (name, lines) in new {
+        let Some(old_lines) = old.get(name) else {
+            report.push(format!("{name}: new file"));
+            continue;
+        };
+
+        let (old_lines, lines) = (normalize(old_lines), normalize(lines));
+
+        if old_lines != lines {
+            report.push(format!("{name}: token stream changed"));
+            report.extend(line_diff(&old_lines, &lines));
+        }
+    }
+
+    report
+}
+
+/// A minimal diff: everything between the common prefix and suffix is shown as
+/// removed and added lines.
+fn line_diff(old: &[String], new: &[String]) -> Vec<String> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let removed = old[prefix..old.len() - suffix]
+        .iter()
+        .map(|line| format!("  - {line}"));
+    let added = new[prefix..new.len() - suffix]
+        .iter()
+        .map(|line| format!("  + {line}"));
+
+    removed.chain(added).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner(source: &str) -> Scanner {
+        Scanner::new(source.to_string())
+    }
+
+    #[test]
+    fn test_fixtures_match_cache() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scan");
+        let cache = fs::read_to_string(dir.join(CACHE_FILE)).unwrap();
+
+        let cached = parse_cache(&cache).unwrap();
+        let report = diff(&cached, &scan_dir(&dir).unwrap(), false);
+
+        assert!(
+            report.is_empty(),
+            "token streams changed; rerun `dev scan-diff tests/scan --update` if intended:\n{}",
+            report.join("\n")
+        );
+    }
+
+    #[test]
+    fn test_diff_ignores_positions() {
+        let old = Dumps::from([("a.lox".to_string(), dump(scanner("1 +\n2")))]);
+        let new = Dumps::from([("a.lox".to_string(), dump(scanner("1 + 2")))]);
+
+        assert_eq!(
+            diff(&old, &new, false),
+            vec![
+                "a.lox: token stream changed",
+                "  - 2\tNumber(2.0)\t\"2\"",
+                "  - 2\tEof\t\"\"",
+                "  + 1\tNumber(2.0)\t\"2\"",
+                "  + 1\tEof\t\"\"",
+            ]
+        );
+        assert!(diff(&old, &new, true).is_empty());
+
+        let old = Dumps::from([("a.lox".to_string(), dump(scanner("1 +\n@")))]);
+        let new = Dumps::from([("a.lox".to_string(), dump(scanner("1 + @")))]);
+
+        assert_eq!(
+            diff(&old, &new, false)[1..],
+            [
+                "  - 2\tEof\t\"\"",
+                "  - 2\terror\tUnexpected character '@'",
+                "  + 1\tEof\t\"\"",
+                "  + 1\terror\tUnexpected character '@'",
+            ]
+        );
+        assert!(diff(&old, &new, true).is_empty());
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dumps = Dumps::from([
+            ("a.lox".to_string(), dump(scanner("1 + 2"))),
+            ("b/c.lox".to_string(), dump(scanner("\""))),
+        ]);
+
+        assert_eq!(parse_cache(&write_cache(&dumps)).unwrap(), dumps);
+    }
+
+    #[test]
+    fn test_cache_format_version() {
+        let cache = write_cache(&Dumps::from([("a.lox".to_string(), dump(scanner("1")))]));
+        let bumped = cache.replacen(
+            &format!("{VERSION_HEADER}{FORMAT_VERSION}"),
+            &format!("{VERSION_HEADER}{}", FORMAT_VERSION + 1),
+            1,
+        );
+
+        assert_eq!(
+            parse_cache(&bumped).unwrap_err().to_string(),
+            format!(
+                "The scan-diff cache has format version {}, but this build reads version {FORMAT_VERSION}",
+                FORMAT_VERSION + 1
+            )
+        );
+
+        let unversioned = cache.lines().skip(1).collect::<Vec<_>>().join("\n");
+        assert_eq!(
+            parse_cache(&unversioned).unwrap_err().to_string(),
+            format!(
+                "The scan-diff cache has no format version, but this build reads version {FORMAT_VERSION}"
+            )
+        );
+    }
+
+    #[test]
+    fn test_cache_malformed() {
+        let cache = format!("{VERSION_HEADER}{FORMAT_VERSION}\n1\tEof\t\"\"\n");
+
+        assert_eq!(
+            parse_cache(&cache).unwrap_err().to_string(),
+            "The scan-diff cache is malformed at line 2; rerun with --update to replace it"
+        );
+    }
+}
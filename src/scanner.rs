@@ -1,17 +1,39 @@
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::iter::FusedIterator;
+use std::str;
 use std::sync::OnceLock;
 
+use crate::diagnostics;
 use crate::Error;
 use crate::Result;
 
-static KEYWORDS: OnceLock<HashMap<&'static str, Token>> = OnceLock::new();
+static KEYWORDS: OnceLock<HashMap<&'static str, TokenKind>> = OnceLock::new();
+
+/// Bytes a streaming scanner keeps buffered past the current character, enough for
+/// the three chars of lookahead the scanner uses.
+const LOOKAHEAD_BYTES: usize = 12;
+
+/// Bytes of already scanned lines a streaming scanner lets build up before dropping
+/// them from its buffer.
+const COMPACT_BYTES: usize = 8 * 1024;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
+pub enum TokenKind {
     Ident(String),
     String(String),
+    /// The part of an interpolated string before a `${`, or between a `}` and the
+    /// next `${`. The final part is an ordinary `String`.
+    Interpolation(String),
     Number(f64),
+    /// The text of a `//` comment after the slashes, only produced in trivia mode.
+    Comment(String),
+    /// The text of a `/* */` comment between its delimiters, only produced in
+    /// trivia mode.
+    BlockComment(String),
     True,
     False,
     Comma,
@@ -19,7 +41,12 @@ pub enum Token {
     Plus,
     Minus,
     Star,
+    Percent,
     Slash,
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
     Eq,
     EqEq,
     Not,
@@ -28,6 +55,11 @@ pub enum Token {
     Ge,
     Lt,
     Le,
+    Amp,
+    Pipe,
+    Caret,
+    Shl,
+    Shr,
     And,
     Or,
     If,
@@ -42,206 +74,646 @@ pub enum Token {
     This,
     Var,
     Print,
+    Break,
+    Continue,
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Semicolon,
+    Question,
+    Colon,
     Eof,
 }
 
-impl fmt::Display for Token {
+impl fmt::Display for TokenKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Token::Ident(s) => write!(f, "{s}"),
-            Token::String(s) => write!(f, "\"{s}\""),
-            Token::Number(n) => write!(f, "{n}"),
-            Token::True => write!(f, "true"),
-            Token::False => write!(f, "false"),
-            Token::Comma => write!(f, ","),
-            Token::Dot => write!(f, "."),
-            Token::Plus => write!(f, "+"),
-            Token::Minus => write!(f, "-"),
-            Token::Star => write!(f, "*"),
-            Token::Slash => write!(f, "/"),
-            Token::Eq => write!(f, "="),
-            Token::EqEq => write!(f, "=="),
-            Token::Not => write!(f, "!"),
-            Token::Ne => write!(f, "!="),
-            Token::Gt => write!(f, ">"),
-            Token::Ge => write!(f, ">="),
-            Token::Lt => write!(f, "<"),
-            Token::Le => write!(f, "<="),
-            Token::And => write!(f, "and"),
-            Token::Or => write!(f, "or"),
-            Token::If => write!(f, "if"),
-            Token::Else => write!(f, "else"),
-            Token::For => write!(f, "for"),
-            Token::While => write!(f, "while"),
-            Token::Nil => write!(f, "nil"),
-            Token::Fun => write!(f, "fun"),
-            Token::Class => write!(f, "class"),
-            Token::Return => write!(f, "return"),
-            Token::Super => write!(f, "super"),
-            Token::This => write!(f, "this"),
-            Token::Var => write!(f, "var"),
-            Token::Print => write!(f, "print"),
-            Token::LeftParen => write!(f, "("),
-            Token::RightParen => write!(f, ")"),
-            Token::LeftBrace => write!(f, "{{"),
-            Token::RightBrace => write!(f, "}}"),
-            Token::Semicolon => write!(f, ";"),
-            Token::Eof => write!(f, "EOF"),
+            TokenKind::Ident(s) => write!(f, "{s}"),
+            TokenKind::String(s) => write!(f, "\"{s}\""),
+            TokenKind::Interpolation(s) => write!(f, "\"{s}${{"),
+            TokenKind::Number(n) => write!(f, "{n}"),
+            TokenKind::Comment(s) => write!(f, "//{s}"),
+            TokenKind::BlockComment(s) => write!(f, "/*{s}*/"),
+            TokenKind::True => write!(f, "true"),
+            TokenKind::False => write!(f, "false"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Dot => write!(f, "."),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Star => write!(f, "*"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::Slash => write!(f, "/"),
+            TokenKind::PlusEq => write!(f, "+="),
+            TokenKind::MinusEq => write!(f, "-="),
+            TokenKind::StarEq => write!(f, "*="),
+            TokenKind::SlashEq => write!(f, "/="),
+            TokenKind::Eq => write!(f, "="),
+            TokenKind::EqEq => write!(f, "=="),
+            TokenKind::Not => write!(f, "!"),
+            TokenKind::Ne => write!(f, "!="),
+            TokenKind::Gt => write!(f, ">"),
+            TokenKind::Ge => write!(f, ">="),
+            TokenKind::Lt => write!(f, "<"),
+            TokenKind::Le => write!(f, "<="),
+            TokenKind::Amp => write!(f, "&"),
+            TokenKind::Pipe => write!(f, "|"),
+            TokenKind::Caret => write!(f, "^"),
+            TokenKind::Shl => write!(f, "<<"),
+            TokenKind::Shr => write!(f, ">>"),
+            TokenKind::And => write!(f, "and"),
+            TokenKind::Or => write!(f, "or"),
+            TokenKind::If => write!(f, "if"),
+            TokenKind::Else => write!(f, "else"),
+            TokenKind::For => write!(f, "for"),
+            TokenKind::While => write!(f, "while"),
+            TokenKind::Nil => write!(f, "nil"),
+            TokenKind::Fun => write!(f, "fun"),
+            TokenKind::Class => write!(f, "class"),
+            TokenKind::Return => write!(f, "return"),
+            TokenKind::Super => write!(f, "super"),
+            TokenKind::This => write!(f, "this"),
+            TokenKind::Var => write!(f, "var"),
+            TokenKind::Print => write!(f, "print"),
+            TokenKind::Break => write!(f, "break"),
+            TokenKind::Continue => write!(f, "continue"),
+            TokenKind::LeftParen => write!(f, "("),
+            TokenKind::RightParen => write!(f, ")"),
+            TokenKind::LeftBrace => write!(f, "{{"),
+            TokenKind::RightBrace => write!(f, "}}"),
+            TokenKind::LeftBracket => write!(f, "["),
+            TokenKind::RightBracket => write!(f, "]"),
+            TokenKind::Semicolon => write!(f, ";"),
+            TokenKind::Question => write!(f, "?"),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Eof => write!(f, "EOF"),
+        }
+    }
+}
+
+impl TokenKind {
+    /// Whether this is a comment, which only a formatter cares about.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Comment(_) | TokenKind::BlockComment(_))
+    }
+}
+
+/// Half-open range of byte offsets into the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// A token along with the text it was scanned from and where it starts.
+/// Lines and columns are 1-based, and columns count chars rather than bytes, so a
+/// tab or a multi-byte character each advance the column by one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub lexeme: String,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Token {
+    pub fn new(
+        kind: TokenKind,
+        lexeme: impl Into<String>,
+        span: Span,
+        line: usize,
+        column: usize,
+    ) -> Self {
+        Self {
+            kind,
+            lexeme: lexeme.into(),
+            span,
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// Everything a scan produced: the tokens, always ending with `Eof`, and every
+/// lexical error encountered along the way.
+#[derive(Debug)]
+pub struct ScanResult {
+    pub tokens: Vec<Token>,
+    pub errors: Vec<Error>,
+}
+
+impl ScanResult {
+    /// The tokens if the scan was clean. A single error is returned as is, several
+    /// are wrapped in [`Error::ScanErrors`].
+    pub fn into_result(mut self) -> Result<Vec<Token>> {
+        match self.errors.len() {
+            0 => Ok(self.tokens),
+            1 => Err(self.errors.remove(0)),
+            _ => Err(Error::ScanErrors {
+                errors: self.errors,
+                omitted: 0,
+            }),
         }
     }
 }
 
+/// Position of a character in the source, recorded so errors can point back at it.
+#[derive(Clone, Copy)]
+struct Mark {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
 pub struct Scanner {
+    /// The source, or when streaming, the part of it read so far that is still
+    /// needed. It always starts at the beginning of a line.
     source: String,
+    /// Byte offset in the whole source of the start of `source`.
+    base: usize,
+    /// Where the rest of the source comes from when streaming.
+    reader: Option<Box<dyn BufRead>>,
+    /// Bytes read that don't make up a whole UTF-8 character yet.
+    pending: Vec<u8>,
     line: usize,
+    column: usize,
+    /// Byte offset of the next character in the whole source, always on a char
+    /// boundary.
     current: usize,
+    /// Byte offset in the whole source of the start of the current line.
+    line_start: usize,
+    multiline_strings: bool,
+    long_string_lines: usize,
+    trivia: bool,
+    /// Errors found while scanning a token that didn't stop it, such as a bad escape
+    /// in a string, waiting to be yielded after it.
+    errors: VecDeque<Error>,
+    /// Where each open `${` began, with how many `{` inside it are still open.
+    interpolations: Vec<(Mark, usize)>,
+    /// Just past the last token or error, where `Eof` goes so that errors at the
+    /// end point after it rather than at a blank line that follows.
+    last_end: Option<Mark>,
+    finished: bool,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
         Self {
             source,
+            base: 0,
+            reader: None,
+            pending: vec![],
             line: 1,
+            column: 1,
             current: 0,
+            line_start: 0,
+            multiline_strings: true,
+            long_string_lines: 3,
+            trivia: false,
+            errors: VecDeque::new(),
+            interpolations: vec![],
+            last_end: None,
+            finished: false,
         }
     }
 
+    /// Scans source read incrementally from `reader`, holding on to little more than
+    /// the line being scanned. Invalid UTF-8 is replaced with U+FFFD, and a read
+    /// error is reported as a scan error that ends the input.
+    pub fn from_reader(reader: impl BufRead + 'static) -> Self {
+        let mut scanner = Self::new(String::new());
+        scanner.reader = Some(Box::new(reader));
+        scanner.fill(LOOKAHEAD_BYTES);
+        scanner
+    }
+
+    /// Whether string literals may span lines (the default, as in the book).
+    /// When disabled, a newline inside a string ends it with an error.
+    pub fn with_multiline_strings(mut self, enabled: bool) -> Self {
+        self.multiline_strings = enabled;
+        self
+    }
+
+    /// How many lines an unterminated string must span before the error suggests
+    /// where the closing quote went missing.
+    pub fn with_long_string_lines(mut self, lines: usize) -> Self {
+        self.long_string_lines = lines;
+        self
+    }
+
+    /// Whether to yield comments as `Comment` and `BlockComment` tokens instead of
+    /// dropping them (the default).
+    pub fn with_trivia(mut self, enabled: bool) -> Self {
+        self.trivia = enabled;
+        self
+    }
+
     pub fn read_tokens(&mut self) -> Result<Vec<Token>> {
-        let mut tokens = vec![];
+        self.scan_all().into_result()
+    }
+
+    /// Scans the whole source, recording each lexical error and carrying on after
+    /// it instead of stopping at the first one. Errors come back in source order.
+    pub fn scan_all(&mut self) -> ScanResult {
+        let mut result = ScanResult {
+            tokens: vec![],
+            errors: vec![],
+        };
 
-        while !self.is_at_end() {
-            if let Some(token) = self.read_token()? {
-                tokens.push(token);
+        for token in self {
+            match token {
+                Ok(token) => result.tokens.push(token),
+                Err(err) => result.errors.push(err),
             }
         }
 
-        tokens.push(Token::Eof);
-        Ok(tokens)
+        diagnostics::tidy(&mut result.errors);
+        result
     }
 
     fn read_token(&mut self) -> Result<Option<Token>> {
-        let token = match self.advance() {
-            '"' => Some(Token::String(self.string_lit()?)),
-            ',' => Some(Token::Comma),
-            '.' => Some(Token::Dot),
-            '+' => Some(Token::Plus),
-            '-' => Some(Token::Minus),
-            '*' => Some(Token::Star),
+        self.compact();
+        let start = self.mark();
+
+        let kind = match self.advance() {
+            '"' => Some(self.string_lit(start)?),
+            'r' if self.next_matches('"') => Some(self.raw_string_lit(start)?),
+            ',' => Some(TokenKind::Comma),
+            '.' => Some(TokenKind::Dot),
+            '+' => {
+                if self.next_matches('=') {
+                    Some(TokenKind::PlusEq)
+                } else {
+                    Some(TokenKind::Plus)
+                }
+            }
+            '-' => {
+                if self.next_matches('=') {
+                    Some(TokenKind::MinusEq)
+                } else {
+                    Some(TokenKind::Minus)
+                }
+            }
+            '*' => {
+                if self.next_matches('=') {
+                    Some(TokenKind::StarEq)
+                } else {
+                    Some(TokenKind::Star)
+                }
+            }
+            '%' => Some(TokenKind::Percent),
             '=' => {
                 if self.next_matches('=') {
-                    Some(Token::EqEq)
+                    Some(TokenKind::EqEq)
                 } else {
-                    Some(Token::Eq)
+                    Some(TokenKind::Eq)
                 }
             }
             '!' => {
                 if self.next_matches('=') {
-                    Some(Token::Ne)
+                    Some(TokenKind::Ne)
                 } else {
-                    Some(Token::Not)
+                    Some(TokenKind::Not)
                 }
             }
             '>' => {
-                if self.next_matches('=') {
-                    Some(Token::Ge)
+                if self.next_matches('>') {
+                    Some(TokenKind::Shr)
+                } else if self.next_matches('=') {
+                    Some(TokenKind::Ge)
                 } else {
-                    Some(Token::Gt)
+                    Some(TokenKind::Gt)
                 }
             }
             '<' => {
-                if self.next_matches('=') {
-                    Some(Token::Le)
+                if self.next_matches('<') {
+                    Some(TokenKind::Shl)
+                } else if self.next_matches('=') {
+                    Some(TokenKind::Le)
                 } else {
-                    Some(Token::Lt)
+                    Some(TokenKind::Lt)
                 }
             }
-            '(' => Some(Token::LeftParen),
-            ')' => Some(Token::RightParen),
-            '{' => Some(Token::LeftBrace),
-            '}' => Some(Token::RightBrace),
-            ';' => Some(Token::Semicolon),
+            '&' => Some(TokenKind::Amp),
+            '|' => Some(TokenKind::Pipe),
+            '^' => Some(TokenKind::Caret),
+            '?' => Some(TokenKind::Question),
+            ':' => Some(TokenKind::Colon),
+            '(' => Some(TokenKind::LeftParen),
+            ')' => Some(TokenKind::RightParen),
+            '[' => Some(TokenKind::LeftBracket),
+            ']' => Some(TokenKind::RightBracket),
+            '{' => {
+                if let Some((_, depth)) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+
+                Some(TokenKind::LeftBrace)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some((_, 0)) => {
+                    self.interpolations.pop();
+                    Some(self.string_lit(start)?)
+                }
+                Some((_, depth)) => {
+                    *depth -= 1;
+                    Some(TokenKind::RightBrace)
+                }
+                None => Some(TokenKind::RightBrace),
+            },
+            ';' => Some(TokenKind::Semicolon),
+            // A shebang line, so scripts can be run directly on Unix.
+            '#' if start.offset == 0 && self.next_matches('!') => {
+                self.comment();
+                None
+            }
             '/' => {
                 if self.next_matches('/') {
                     self.comment();
-                    None
+                    let text = self.text(start.offset + 2, self.current);
+                    self.trivia.then(|| TokenKind::Comment(text.to_string()))
+                } else if self.next_matches('*') {
+                    self.block_comment(start)?;
+                    let text = self.text(start.offset + 2, self.current - 2);
+                    self.trivia
+                        .then(|| TokenKind::BlockComment(text.to_string()))
+                } else if self.next_matches('=') {
+                    Some(TokenKind::SlashEq)
                 } else {
-                    Some(Token::Slash)
+                    Some(TokenKind::Slash)
                 }
             }
-            ' ' | '\r' | '\t' => None,
-            '\n' => {
-                self.line += 1;
-                None
-            }
-            c if c.is_digit(10) => Some(Token::Number(self.number_lit(c)?)),
+            ' ' | '\r' | '\t' | '\n' => None,
+            c if c.is_ascii_digit() => Some(TokenKind::Number(self.number_lit(start)?)),
             c if is_valid_identifier_start(c) => {
                 let ident = self.identifier(c);
-                keyword(&ident).cloned().or(Some(Token::Ident(ident)))
+                keyword(&ident).cloned().or(Some(TokenKind::Ident(ident)))
             }
             c => {
-                return Err(Error::message(
-                    &self.source,
-                    &format!("Unexpected character \'{c}\'"),
-                ));
+                return Err(self.error_from(start, &format!("Unexpected character \'{c}\'")));
             }
         };
 
-        Ok(token)
+        Ok(kind.map(|kind| {
+            let span = Span::new(start.offset, self.current);
+            let lexeme = self.text(span.start, span.end);
+            Token::new(kind, lexeme, span, start.line, start.column)
+        }))
     }
 
-    fn string_lit(&mut self) -> Result<String> {
+    /// Reads a string literal up to its closing quote, or up to a `${` starting an
+    /// interpolated expression. `start` is the opening quote, or the `}` ending the
+    /// previous interpolation.
+    fn string_lit(&mut self, start: Mark) -> Result<TokenKind> {
         let mut s = String::new();
 
         while !matches!(self.peek(), Some('"')) && !self.is_at_end() {
-            if matches!(self.peek(), Some('\n')) {
-                // Multi-line string handling
-                self.line += 1;
+            if matches!(self.peek(), Some('\n')) && !self.multiline_strings {
+                return Err(self.error_from(
+                    start,
+                    "Unterminated string (multi-line strings are disabled)",
+                ));
             }
 
-            s.push(self.advance());
+            let escape_start = self.mark();
+
+            match self.advance() {
+                '\\' if !self.is_at_end() => match self.escape(escape_start) {
+                    Ok(c) => s.push(c),
+                    // Keep going so a bad escape doesn't end the string early and
+                    // turn the rest of it into bogus tokens.
+                    Err(err) => {
+                        self.errors.push_back(err);
+                        s.push(char::REPLACEMENT_CHARACTER);
+                    }
+                },
+                '$' if self.next_matches('{') => {
+                    self.interpolations.push((escape_start, 0));
+                    return Ok(TokenKind::Interpolation(s));
+                }
+                c => s.push(c),
+            }
         }
 
         if self.is_at_end() {
-            return Err(Error::message(
-                &self.source,
-                &format!("Unterminated string"),
-            ));
+            return Err(self.unterminated_string(start));
         }
 
         self.advance();
-        Ok(s)
+        Ok(TokenKind::String(s))
     }
 
-    fn number_lit(&mut self, c: char) -> Result<f64> {
+    /// Reads a raw string literal such as `r"C:\path"`, where backslashes and `${`
+    /// are kept as written and the only way to end it is the closing quote.
+    fn raw_string_lit(&mut self, start: Mark) -> Result<TokenKind> {
         let mut s = String::new();
-        s.push(c);
 
-        while self.peek().is_some_and(|c| c.is_digit(10)) {
+        while !matches!(self.peek(), Some('"')) && !self.is_at_end() {
+            if matches!(self.peek(), Some('\n')) && !self.multiline_strings {
+                return Err(self.error_from(
+                    start,
+                    "Unterminated string (multi-line strings are disabled)",
+                ));
+            }
+
             s.push(self.advance());
         }
 
-        if matches!(self.peek(), Some('.')) && self.peek_next().is_some_and(|c| c.is_digit(10)) {
-            s.push(self.advance());
+        if self.is_at_end() {
+            return Err(self.unterminated_string(start));
+        }
 
-            while self.peek().is_some_and(|c| c.is_digit(10)) {
-                s.push(self.advance());
+        self.advance();
+        Ok(TokenKind::String(s))
+    }
+
+    fn unterminated_string(&mut self, start: Mark) -> Error {
+        let text = self.text(start.offset, self.current).replace('\n', "\\n");
+        let mut message = format!(
+            "Unterminated string starting at line {}, column {}: {}",
+            start.line,
+            start.column,
+            diagnostics::quote(&text)
+        );
+
+        if let Some(line) = self.missing_quote_line(start) {
+            message.push_str(&format!(
+                " (did you forget a closing quote before line {line}?)"
+            ));
+        }
+
+        self.error_from(start, &message)
+    }
+
+    /// For a runaway string spanning more than `long_string_lines` lines, the first
+    /// line inside it that looks like code, i.e. ends with `;` or `{`.
+    fn missing_quote_line(&self, start: Mark) -> Option<usize> {
+        if self.line - start.line < self.long_string_lines {
+            return None;
+        }
+
+        self.text(start.offset, self.current)
+            .lines()
+            .skip(1)
+            .position(|line| line.trim_end().ends_with([';', '{']))
+            .map(|i| start.line + i + 1)
+    }
+
+    /// Translates the escape sequence following a backslash in a string literal.
+    fn escape(&mut self, start: Mark) -> Result<char> {
+        let c = match self.advance() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            'u' => return self.unicode_escape(start),
+            c => {
+                // A control char such as a newline is spelled out so the message
+                // stays on one line.
+                let c = if c.is_control() {
+                    c.escape_debug().to_string()
+                } else {
+                    c.to_string()
+                };
+
+                return Err(self.error_from(start, &format!("Unknown escape sequence '\\{c}'")));
+            }
+        };
+
+        Ok(c)
+    }
+
+    /// Reads the `{XXXX}` part of a `\u{XXXX}` escape: one to six hex digits naming a
+    /// Unicode scalar value.
+    fn unicode_escape(&mut self, start: Mark) -> Result<char> {
+        let mut escape = "\\u".to_string();
+        let mut digits = String::new();
+
+        if self.next_matches('{') {
+            escape.push('{');
+
+            while self
+                .peek()
+                .is_some_and(|c| c != '}' && c != '"' && c != '\n')
+            {
+                digits.push(self.advance());
+            }
+
+            escape.push_str(&digits);
+
+            if self.next_matches('}') {
+                escape.push('}');
+
+                let c = u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .filter(|_| digits.len() <= 6 && digits.chars().all(|c| c.is_ascii_hexdigit()))
+                    .and_then(char::from_u32);
+
+                if let Some(c) = c {
+                    return Ok(c);
+                }
             }
         }
 
-        let number = s.parse::<f64>()?;
+        Err(self.error_from(
+            start,
+            &format!("Invalid unicode escape {}", diagnostics::quote(&escape)),
+        ))
+    }
+
+    /// Reads the rest of a number literal whose first digit is at `start`, including
+    /// an optional exponent (`2.5e-3`). Digits may be separated by single
+    /// underscores, as in `1_000_000` or `3.141_592`.
+    fn number_lit(&mut self, start: Mark) -> Result<f64> {
+        self.digits();
+
+        if matches!(self.peek(), Some('.'))
+            && self
+                .peek_next()
+                .is_some_and(|c| c.is_ascii_digit() || c == '_')
+        {
+            self.advance();
+            self.digits();
+        }
+
+        // `e` only starts an exponent when digits follow, so `1else` is `1` `else`.
+        let exponent = matches!(self.peek(), Some('e' | 'E'))
+            && match self.peek_next() {
+                Some('+' | '-') => self.peek_third().is_some_and(|c| c.is_ascii_digit()),
+                c => c.is_some_and(|c| c.is_ascii_digit()),
+            };
+
+        if exponent {
+            self.advance();
+
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.advance();
+            }
+
+            self.digits();
+        }
+
+        let literal = self.text(start.offset, self.current);
+        let problem = if literal.contains("__") {
+            Some("'_' can't be doubled")
+        } else if literal.ends_with('_') {
+            Some("a number can't end with '_'")
+        } else if literal.contains("_.") || literal.contains("._") {
+            Some("'_' can't be next to the decimal point")
+        } else if literal.contains("_e") || literal.contains("_E") {
+            Some("'_' can't be next to the exponent")
+        } else {
+            None
+        };
+
+        if let Some(problem) = problem {
+            return Err(self.error_from(
+                start,
+                &format!(
+                    "Invalid number literal {}: {problem}",
+                    diagnostics::quote(literal)
+                ),
+            ));
+        }
+
+        let number = literal.replace('_', "").parse::<f64>()?;
         Ok(number)
     }
 
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '_') {
+            self.advance();
+        }
+    }
+
     fn identifier(&mut self, c: char) -> String {
         let mut s = String::new();
         s.push(c);
 
-        while self.peek().is_some_and(|c| is_valid_identifier_char(c)) {
+        while self.peek().is_some_and(is_valid_identifier_char) {
             s.push(self.advance());
         }
 
@@ -254,69 +726,1262 @@ impl Scanner {
         }
     }
 
+    /// Skips a `/* ... */` comment, which may contain nested block comments.
+    fn block_comment(&mut self, start: Mark) -> Result<()> {
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(self.error_from(start, "Unterminated block comment"));
+            }
+
+            match self.advance() {
+                '/' if self.next_matches('*') => depth += 1,
+                '*' if self.next_matches('/') => depth -= 1,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mark(&self) -> Mark {
+        Mark {
+            offset: self.current,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// An error positioned at `start` and spanning everything scanned since. Only
+    /// the line `start` is on is kept as context.
+    fn error_from(&mut self, start: Mark, message: &str) -> Error {
+        self.fill_line();
+
+        Error::message_with_span(
+            Span::new(start.offset, self.current),
+            start.line,
+            start.column,
+            self.line_at(start.offset),
+            message,
+        )
+    }
+
+    /// The line of source containing the byte at `offset`, without its line ending.
+    fn line_at(&self, offset: usize) -> &str {
+        let offset = offset - self.base;
+        let begin = self.source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.source[offset..]
+            .find('\n')
+            .map_or(self.source.len(), |i| offset + i);
+
+        self.source[begin..end].trim_end_matches('\r')
+    }
+
+    /// The source between two byte offsets, which must still be buffered.
+    fn text(&self, start: usize, end: usize) -> &str {
+        &self.source[start - self.base..end - self.base]
+    }
+
+    /// Reads until at least `bytes` bytes past the current character are buffered,
+    /// or the reader runs out.
+    fn fill(&mut self, bytes: usize) {
+        while self.source.len() - (self.current - self.base) < bytes && self.read_chunk() {}
+    }
+
+    /// Reads until the rest of the current line is buffered, so an error can show it.
+    fn fill_line(&mut self) {
+        let mut from = self.current - self.base;
+
+        while !self.source[from..].contains('\n') {
+            from = self.source.len();
+
+            if !self.read_chunk() {
+                break;
+            }
+        }
+    }
+
+    /// Appends the next chunk from the reader to the buffer, returning whether there
+    /// may be more to read.
+    fn read_chunk(&mut self) -> bool {
+        let Some(reader) = self.reader.as_mut() else {
+            return false;
+        };
+
+        let len = match reader.fill_buf() {
+            Ok(chunk) => {
+                self.pending.extend_from_slice(chunk);
+                chunk.len()
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => return true,
+            Err(err) => {
+                self.errors.push_back(err.into());
+                0
+            }
+        };
+
+        reader.consume(len);
+
+        if len == 0 {
+            self.reader = None;
+
+            if !self.pending.is_empty() {
+                self.pending.clear();
+                self.source.push(char::REPLACEMENT_CHARACTER);
+            }
+
+            return false;
+        }
+
+        loop {
+            match str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    self.source.push_str(text);
+                    self.pending.clear();
+                    break;
+                }
+                Err(err) => {
+                    let valid = err.valid_up_to();
+                    self.source
+                        .push_str(str::from_utf8(&self.pending[..valid]).unwrap_or_default());
+
+                    // An incomplete character at the end waits for the next chunk.
+                    let Some(invalid) = err.error_len() else {
+                        self.pending.drain(..valid);
+                        break;
+                    };
+
+                    self.source.push(char::REPLACEMENT_CHARACTER);
+                    self.pending.drain(..valid + invalid);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Drops lines that have been scanned from a streaming scanner's buffer, keeping
+    /// the line the next token starts on and anything an open `${` still needs.
+    fn compact(&mut self) {
+        if self.reader.is_none() {
+            return;
+        }
+
+        let keep = match self.interpolations.first() {
+            Some((start, _)) => {
+                let offset = start.offset - self.base;
+                self.base + self.source[..offset].rfind('\n').map_or(0, |i| i + 1)
+            }
+            None => self.line_start,
+        } - self.base;
+
+        if keep >= COMPACT_BYTES {
+            self.source.drain(..keep);
+            self.base += keep;
+        }
+    }
+
     fn next_matches(&mut self, c: char) -> bool {
         if self.is_at_end() {
             return false;
         }
 
-        if !self.peek().is_some_and(|next| next == c) {
+        if self.peek().is_none_or(|next| next != c) {
             return false;
         }
 
-        self.current += 1;
+        self.advance();
         true
     }
 
+    /// Consumes the next character. Callers check `is_at_end` first; at the end
+    /// this returns `'\0'` and consumes nothing.
     fn advance(&mut self) -> char {
-        let c = self.peek().expect("index into source string is invalid");
-        self.current += 1;
+        let Some(c) = self.peek() else {
+            return '\0';
+        };
+
+        self.current += c.len_utf8();
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+            self.line_start = self.current;
+        } else {
+            self.column += 1;
+        }
+
+        if self.reader.is_some() {
+            self.fill(LOOKAHEAD_BYTES);
+        }
+
         c
     }
 
     fn peek(&self) -> Option<char> {
-        self.source.chars().nth(self.current)
+        self.source[self.current - self.base..].chars().next()
     }
 
     fn peek_next(&self) -> Option<char> {
-        self.source.chars().nth(self.current + 1)
+        self.source[self.current - self.base..].chars().nth(1)
+    }
+
+    fn peek_third(&self) -> Option<char> {
+        self.source[self.current - self.base..].chars().nth(2)
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        self.current - self.base >= self.source.len()
     }
 }
 
+/// Yields tokens lazily, ending with a single `Eof`. An error is yielded in place
+/// of the bad input and scanning carries on after it.
+impl Iterator for Scanner {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(err) = self.errors.pop_front() {
+                return Some(Err(err));
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            if self.is_at_end() {
+                if let Some((start, _)) = self.interpolations.pop() {
+                    return Some(Err(self.error_from(start, "Unterminated interpolation")));
+                }
+
+                self.finished = true;
+
+                let end = self.last_end.unwrap_or_else(|| self.mark());
+                return Some(Ok(Token::new(
+                    TokenKind::Eof,
+                    "",
+                    Span::new(end.offset, end.offset),
+                    end.line,
+                    end.column,
+                )));
+            }
+
+            match self.read_token() {
+                Ok(Some(token)) => {
+                    self.last_end = Some(self.mark());
+                    return Some(Ok(token));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    self.last_end = Some(self.mark());
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl FusedIterator for Scanner {}
+
+/// Identifiers follow UAX #31: they start with an `XID_Start` character or `_`
+/// and continue with `XID_Continue` characters, which include digits.
 fn is_valid_identifier_start(c: char) -> bool {
-    c.is_ascii_alphabetic() || c == '_'
+    unicode_ident::is_xid_start(c) || c == '_'
 }
 
 fn is_valid_identifier_char(c: char) -> bool {
-    is_valid_identifier_start(c) || c.is_digit(10)
+    unicode_ident::is_xid_continue(c)
 }
 
-fn keyword(s: &str) -> Option<&Token> {
-    KEYWORDS
-        .get_or_init(|| {
-            let mut keywords = HashMap::new();
-
-            keywords.insert("true", Token::True);
-            keywords.insert("false", Token::False);
-            keywords.insert("and", Token::And);
-            keywords.insert("or", Token::Or);
-            keywords.insert("if", Token::If);
-            keywords.insert("else", Token::Else);
-            keywords.insert("for", Token::For);
-            keywords.insert("while", Token::While);
-            keywords.insert("nil", Token::Nil);
-            keywords.insert("fun", Token::Fun);
-            keywords.insert("class", Token::Class);
-            keywords.insert("return", Token::Return);
-            keywords.insert("super", Token::Super);
-            keywords.insert("this", Token::This);
-            keywords.insert("var", Token::Var);
-            keywords.insert("print", Token::Print);
-
-            keywords
-        })
-        .get(s)
+fn keyword(s: &str) -> Option<&TokenKind> {
+    keywords().get(s)
+}
+
+pub fn keyword_names() -> impl Iterator<Item = &'static str> {
+    keywords().keys().copied()
+}
+
+fn keywords() -> &'static HashMap<&'static str, TokenKind> {
+    KEYWORDS.get_or_init(|| {
+        let mut keywords = HashMap::new();
+
+        keywords.insert("true", TokenKind::True);
+        keywords.insert("false", TokenKind::False);
+        keywords.insert("and", TokenKind::And);
+        keywords.insert("or", TokenKind::Or);
+        keywords.insert("if", TokenKind::If);
+        keywords.insert("else", TokenKind::Else);
+        keywords.insert("for", TokenKind::For);
+        keywords.insert("while", TokenKind::While);
+        keywords.insert("nil", TokenKind::Nil);
+        keywords.insert("fun", TokenKind::Fun);
+        keywords.insert("class", TokenKind::Class);
+        keywords.insert("return", TokenKind::Return);
+        keywords.insert("super", TokenKind::Super);
+        keywords.insert("this", TokenKind::This);
+        keywords.insert("var", TokenKind::Var);
+        keywords.insert("print", TokenKind::Print);
+        keywords.insert("break", TokenKind::Break);
+        keywords.insert("continue", TokenKind::Continue);
+
+        keywords
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        Scanner::new(source.to_string())
+            .read_tokens()
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    /// Scans `source`, expecting a single error, and returns its line and message.
+    fn message(source: &str) -> (usize, String) {
+        match Scanner::new(source.to_string()).read_tokens() {
+            Err(Error::InterpretErrorWithIndex {
+                line_index,
+                message,
+                ..
+            }) => (line_index, message),
+            result => panic!("expected a scan error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_token_positions() {
+        let source = "// one\n\n  (answer ==\n42)".to_string();
+        let tokens = Scanner::new(source).read_tokens().unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::LeftParen, "(", Span::new(10, 11), 3, 3),
+                Token::new(
+                    TokenKind::Ident("answer".to_string()),
+                    "answer",
+                    Span::new(11, 17),
+                    3,
+                    4
+                ),
+                Token::new(TokenKind::EqEq, "==", Span::new(18, 20), 3, 11),
+                Token::new(TokenKind::Number(42.0), "42", Span::new(21, 23), 4, 1),
+                Token::new(TokenKind::RightParen, ")", Span::new(23, 24), 4, 3),
+                Token::new(TokenKind::Eof, "", Span::new(24, 24), 4, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiline_strings() {
+        let source = "\"one\ntwo\"".to_string();
+
+        assert_eq!(
+            kinds(&source),
+            vec![TokenKind::String("one\ntwo".to_string()), TokenKind::Eof]
+        );
+
+        let err = Scanner::new(source)
+            .with_multiline_strings(false)
+            .read_tokens()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unterminated string (multi-line strings are disabled)"));
+    }
+
+    #[test]
+    fn test_large_source() {
+        let source = "(1.5 + \"two\") // comment\n".repeat(50_000);
+        let tokens = Scanner::new(source).read_tokens().unwrap();
+
+        assert_eq!(tokens.len(), 5 * 50_000 + 1);
+    }
+
+    #[test]
+    fn test_multibyte_strings_and_comments() {
+        let source = "\"héllo wörld ✓\" // ünïcode ✓ comment\n+ 1";
+
+        assert_eq!(
+            kinds(source),
+            vec![
+                TokenKind::String("héllo wörld ✓".to_string()),
+                TokenKind::Plus,
+                TokenKind::Number(1.0),
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multibyte_source_keeps_trailing_tokens() {
+        let source = "var s = \"héllo\"; // ünïcode comment\nprint s;";
+        let tokens = Scanner::new(source.to_string()).read_tokens().unwrap();
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| token.lexeme.as_str())
+                .collect::<Vec<_>>(),
+            vec!["var", "s", "=", "\"héllo\"", ";", "print", "s", ";", ""]
+        );
+        assert_eq!(
+            tokens[5].span,
+            Span::new(
+                source.find("print").unwrap(),
+                source.find("print").unwrap() + 5
+            )
+        );
+
+        assert_eq!(
+            kinds("1 + \"ü\" + π"),
+            vec![
+                TokenKind::Number(1.0),
+                TokenKind::Plus,
+                TokenKind::String("ü".to_string()),
+                TokenKind::Plus,
+                TokenKind::Ident("π".to_string()),
+                TokenKind::Eof
+            ]
+        );
+        assert!(Scanner::new("\"unterminated ü".to_string())
+            .read_tokens()
+            .is_err());
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let source = "1 /* outer /* inner */ still // comment * / */ + /**/ 2";
+        assert_eq!(
+            kinds(source),
+            vec![
+                TokenKind::Number(1.0),
+                TokenKind::Plus,
+                TokenKind::Number(2.0),
+                TokenKind::Eof
+            ]
+        );
+
+        let tokens = Scanner::new("/* one\ntwo */\n3".to_string())
+            .read_tokens()
+            .unwrap();
+        assert_eq!(tokens[0].line, 3);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let source = "1\n/* outer /* inner */\n\n".to_string();
+        let err = Scanner::new(source).read_tokens().unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::InterpretErrorWithIndex {
+                line_index: 2,
+                column: Some(1),
+                span: Some(Span { start: 2, end: 24 }),
+                ref message,
+                ..
+            } if message == "Unterminated block comment"
+        ));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let source = r#""\n\t\r\\\"\0 \u{48}\u{e9}\u{1F600} \\""#;
+        assert_eq!(
+            kinds(source),
+            vec![
+                TokenKind::String("\n\t\r\\\"\0 Hé😀 \\".to_string()),
+                TokenKind::Eof
+            ]
+        );
+
+        assert_eq!(
+            kinds("\"multi\\t\nline\""),
+            vec![
+                TokenKind::String("multi\t\nline".to_string()),
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        assert_eq!(
+            message("\n\"\\q\""),
+            (2, "Unknown escape sequence '\\q'".to_string())
+        );
+        assert_eq!(
+            message("\"a\\\nb\""),
+            (1, "Unknown escape sequence '\\\\n'".to_string())
+        );
+        let err = Scanner::new("\"a\\\nb\"".to_string())
+            .read_tokens()
+            .unwrap_err();
+        assert!(!err.to_string().contains('\n'), "{err}");
+        assert_eq!(
+            message(r#""\u{}""#),
+            (1, "Invalid unicode escape '\\u{}'".to_string())
+        );
+        assert_eq!(
+            message(r#""\u{110000}""#),
+            (1, "Invalid unicode escape '\\u{110000}'".to_string())
+        );
+        assert_eq!(
+            message(r#""\u{zz}""#),
+            (1, "Invalid unicode escape '\\u{zz}'".to_string())
+        );
+        assert_eq!(
+            message(r#""\u0041""#),
+            (1, "Invalid unicode escape '\\u'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_columns_count_chars() {
+        let source = "\t\"é✓\" +\n\t\t\"ü\" == 1";
+        let tokens = Scanner::new(source.to_string()).read_tokens().unwrap();
+        let positions = tokens
+            .iter()
+            .map(|token| (token.line, token.column, token.span))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            positions,
+            vec![
+                (1, 2, Span::new(1, 8)),
+                (1, 7, Span::new(9, 10)),
+                (2, 3, Span::new(13, 17)),
+                (2, 7, Span::new(18, 20)),
+                (2, 10, Span::new(21, 22)),
+                (2, 11, Span::new(22, 22)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let err = Scanner::new("\"é\" $".to_string())
+            .read_tokens()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InterpretErrorWithIndex {
+                line_index: 1,
+                column: Some(5),
+                span: Some(Span { start: 5, end: 6 }),
+                ..
+            }
+        ));
+        assert!(err.to_string().starts_with("Error interpreting line [1:5]"));
+
+        let err = Scanner::new("1 +\n  \"two".to_string())
+            .read_tokens()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InterpretErrorWithIndex {
+                line_index: 2,
+                column: Some(3),
+                span: Some(Span { start: 6, end: 10 }),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_error_shows_only_its_line() {
+        let source = (1..=10)
+            .map(|line| match line {
+                7 => "  1 + @ // line seven".to_string(),
+                line => format!("// line {line}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        let err = Scanner::new(source).read_tokens().unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Error interpreting line [7:7] \"  1 + @ // line seven\": Unexpected character '@'"
+        );
+        assert!(!err.to_string().contains("line 1"));
+
+        let long = format!("{} @ {}", "1 +".repeat(100), "+ 2".repeat(100));
+        let err = Scanner::new(long).read_tokens().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Error interpreting line [1:302] \"…{} @ {}…\": Unexpected character '@'",
+                &"1 +".repeat(100)[241..],
+                &"+ 2".repeat(100)[..58]
+            )
+        );
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let result = Scanner::new("1 $\n2 + @ 3\n\"ok\" # 4".to_string()).scan_all();
+        let errors = result
+            .errors
+            .iter()
+            .map(|err| match err {
+                Error::InterpretErrorWithIndex {
+                    line_index,
+                    message,
+                    ..
+                } => (*line_index, message.as_str()),
+                err => panic!("unexpected error {err:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                (1, "Unexpected character '$'"),
+                (2, "Unexpected character '@'"),
+                (3, "Unexpected character '#'"),
+            ]
+        );
+        assert_eq!(result.tokens.len(), 7);
+        assert!(matches!(
+            result.into_result(),
+            Err(Error::ScanErrors { errors, .. }) if errors.len() == 3
+        ));
+    }
+
+    #[test]
+    fn test_recovers_after_bad_strings() {
+        let result = Scanner::new("\"one\n1 + 2\n\"t\\qo\" $".to_string())
+            .with_multiline_strings(false)
+            .scan_all();
+        let kinds = result
+            .tokens
+            .into_iter()
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+        let lines = result
+            .errors
+            .iter()
+            .map(|err| match err {
+                Error::InterpretErrorWithIndex { line_index, .. } => *line_index,
+                err => panic!("unexpected error {err:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Number(1.0),
+                TokenKind::Plus,
+                TokenKind::Number(2.0),
+                TokenKind::String("t\u{FFFD}o".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(lines, vec![1, 3, 3]);
+    }
+
+    #[test]
+    fn test_missing_quote_note() {
+        let source = include_str!("../tests/regressions/scan_err_missing_closing_quote.lox");
+        let err = Scanner::new(source.to_string()).read_tokens().unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::InterpretErrorWithIndex {
+                line_index: 3,
+                column: Some(3),
+                ref message,
+                ..
+            } if message == "Unterminated string starting at line 3, column 3: \
+                '\"hello\\n  2 * 3;\\n  (4 + 5) {\\n …' (truncated, 36 chars) \
+                (did you forget a closing quote before line 4?)"
+        ));
+
+        let err = Scanner::new(source.to_string())
+            .with_long_string_lines(10)
+            .read_tokens()
+            .unwrap_err();
+        assert!(err.to_string().ends_with(
+            ": Unterminated string starting at line 3, column 3: \
+             '\"hello\\n  2 * 3;\\n  (4 + 5) {\\n …' (truncated, 36 chars)"
+        ));
+
+        let err = Scanner::new("\"one\ntwo\nthree\nfour".to_string())
+            .read_tokens()
+            .unwrap_err();
+        assert!(err.to_string().ends_with(
+            ": Unterminated string starting at line 1, column 1: '\"one\\ntwo\\nthree\\nfour'"
+        ));
+    }
+
+    #[test]
+    fn test_unterminated_string_start() {
+        let source = (1..=10)
+            .map(|line| match line {
+                3 => "  x = \"a runaway string that keeps going well past the excerpt".to_string(),
+                line => format!("  line {line}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let err = Scanner::new(source).read_tokens().unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::InterpretErrorWithIndex {
+                line_index: 3,
+                column: Some(7),
+                ref message,
+                ..
+            } if message == "Unterminated string starting at line 3, column 7: \
+                '\"a runaway string that keeps goi…' (truncated, 127 chars)"
+        ));
+    }
+
+    #[test]
+    fn test_iterator() {
+        let mut scanner = Scanner::new("1 $ 2".to_string());
+
+        assert_eq!(
+            scanner.next().unwrap().unwrap().kind,
+            TokenKind::Number(1.0)
+        );
+        assert!(scanner.next().unwrap().is_err());
+        assert_eq!(
+            scanner.next().unwrap().unwrap().kind,
+            TokenKind::Number(2.0)
+        );
+        assert_eq!(scanner.next().unwrap().unwrap().kind, TokenKind::Eof);
+        assert!(scanner.next().is_none());
+        assert!(scanner.next().is_none());
+
+        let tokens = Scanner::new("(1 + \"a\\qb\")".to_string())
+            .map(|token| token.map(|token| token.kind))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            tokens.as_slice(),
+            [
+                Ok(TokenKind::LeftParen),
+                Ok(TokenKind::Number(_)),
+                Ok(TokenKind::Plus),
+                Ok(TokenKind::String(_)),
+                Err(_),
+                Ok(TokenKind::RightParen),
+                Ok(TokenKind::Eof),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_iterator_collect() {
+        let tokens = Scanner::new("1 + 2".to_string())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            tokens.iter().map(|token| &token.kind).collect::<Vec<_>>(),
+            vec![
+                &TokenKind::Number(1.0),
+                &TokenKind::Plus,
+                &TokenKind::Number(2.0),
+                &TokenKind::Eof
+            ]
+        );
+
+        assert!(Scanner::new("1 + @".to_string())
+            .collect::<Result<Vec<_>>>()
+            .is_err());
+        assert_eq!(Scanner::new(String::new()).count(), 1);
+    }
+
+    #[test]
+    fn test_number_separators() {
+        assert_eq!(
+            kinds("1_000_000 0.125_5 1_0.0_1"),
+            vec![
+                TokenKind::Number(1_000_000.0),
+                TokenKind::Number(0.125_5),
+                TokenKind::Number(10.01),
+                TokenKind::Eof
+            ]
+        );
+        assert_eq!(
+            kinds("_1"),
+            vec![TokenKind::Ident("_1".to_string()), TokenKind::Eof]
+        );
+
+        assert_eq!(
+            message("1__0"),
+            (
+                1,
+                "Invalid number literal '1__0': '_' can't be doubled".to_string()
+            )
+        );
+        assert_eq!(
+            message("10_"),
+            (
+                1,
+                "Invalid number literal '10_': a number can't end with '_'".to_string()
+            )
+        );
+        assert_eq!(
+            message("1_.5"),
+            (
+                1,
+                "Invalid number literal '1_.5': '_' can't be next to the decimal point".to_string()
+            )
+        );
+        assert_eq!(
+            message("1._5"),
+            (
+                1,
+                "Invalid number literal '1._5': '_' can't be next to the decimal point".to_string()
+            )
+        );
+        assert_eq!(
+            message("1_x"),
+            (
+                1,
+                "Invalid number literal '1_': a number can't end with '_'".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_number_exponents() {
+        for source in ["1e9", "2.5e-3", "1E+6", "6.02e23", "1_000e1_0", "5e0"] {
+            assert_eq!(
+                kinds(source),
+                vec![
+                    TokenKind::Number(source.replace('_', "").parse().unwrap()),
+                    TokenKind::Eof
+                ],
+                "{source}"
+            );
+        }
+
+        // Without digits after it, `e` isn't an exponent and the number ends there.
+        let number = |n: f64| TokenKind::Number(n);
+        let ident = |name: &str| TokenKind::Ident(name.to_string());
+        for (source, expected) in [
+            ("1else", vec![number(1.0), TokenKind::Else]),
+            ("1each", vec![number(1.0), ident("each")]),
+            ("1e", vec![number(1.0), ident("e")]),
+            ("1e+", vec![number(1.0), ident("e"), TokenKind::Plus]),
+            (
+                "2.5E- 1",
+                vec![number(2.5), ident("E"), TokenKind::Minus, number(1.0)],
+            ),
+            ("1e_5", vec![number(1.0), ident("e_5")]),
+        ] {
+            let mut expected = expected;
+            expected.push(TokenKind::Eof);
+            assert_eq!(kinds(source), expected, "{source}");
+        }
+
+        assert_eq!(
+            message("1_e5"),
+            (
+                1,
+                "Invalid number literal '1_e5': '_' can't be next to the exponent".to_string()
+            )
+        );
+        assert_eq!(
+            message("1e5_"),
+            (
+                1,
+                "Invalid number literal '1e5_': a number can't end with '_'".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let piece = |s: &str| TokenKind::Interpolation(s.to_string());
+        let string = |s: &str| TokenKind::String(s.to_string());
+        let ident = |s: &str| TokenKind::Ident(s.to_string());
+
+        assert_eq!(
+            kinds("\"hello, ${name}!\""),
+            vec![piece("hello, "), ident("name"), string("!"), TokenKind::Eof]
+        );
+        assert_eq!(
+            kinds("\"${a}$5 ${b}\""),
+            vec![
+                piece(""),
+                ident("a"),
+                piece("$5 "),
+                ident("b"),
+                string(""),
+                TokenKind::Eof
+            ]
+        );
+        assert_eq!(
+            kinds("\"a ${ {} } ${\"b${c}\"} \\${d}\""),
+            vec![
+                piece("a "),
+                TokenKind::LeftBrace,
+                TokenKind::RightBrace,
+                piece(" "),
+                piece("b"),
+                ident("c"),
+                string(""),
+                string(" ${d}"),
+                TokenKind::Eof
+            ]
+        );
+
+        let tokens = Scanner::new("\"x ${1} y\"".to_string())
+            .read_tokens()
+            .unwrap();
+        let lexemes = tokens
+            .iter()
+            .map(|token| token.lexeme.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lexemes, vec!["\"x ${", "1", "} y\"", ""]);
+    }
+
+    #[test]
+    fn test_unterminated_interpolation() {
+        let err = Scanner::new("\"one\n  ${1 +\n2\n".to_string())
+            .read_tokens()
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::InterpretErrorWithIndex {
+                line_index: 2,
+                column: Some(3),
+                ref message,
+                ..
+            } if message == "Unterminated interpolation"
+        ));
+
+        // The unclosed `${`s are found innermost first but reported in source order.
+        let result = Scanner::new("\"a ${ \"b ${1".to_string()).scan_all();
+        assert_eq!(
+            result
+                .errors
+                .iter()
+                .map(|err| match err {
+                    Error::InterpretErrorWithIndex { column, .. } => *column,
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            vec![Some(4), Some(10)]
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        assert_eq!(
+            kinds("a >> b >= c << d <= e & f | g ^ h > i"),
+            vec![
+                TokenKind::Ident("a".to_string()),
+                TokenKind::Shr,
+                TokenKind::Ident("b".to_string()),
+                TokenKind::Ge,
+                TokenKind::Ident("c".to_string()),
+                TokenKind::Shl,
+                TokenKind::Ident("d".to_string()),
+                TokenKind::Le,
+                TokenKind::Ident("e".to_string()),
+                TokenKind::Amp,
+                TokenKind::Ident("f".to_string()),
+                TokenKind::Pipe,
+                TokenKind::Ident("g".to_string()),
+                TokenKind::Caret,
+                TokenKind::Ident("h".to_string()),
+                TokenKind::Gt,
+                TokenKind::Ident("i".to_string()),
+                TokenKind::Eof
+            ]
+        );
+        assert_eq!(
+            kinds(">>= <<="),
+            vec![
+                TokenKind::Shr,
+                TokenKind::Eq,
+                TokenKind::Shl,
+                TokenKind::Eq,
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment_operators() {
+        assert_eq!(
+            kinds("+= -= *= /= + = /* c */ /=1 //="),
+            vec![
+                TokenKind::PlusEq,
+                TokenKind::MinusEq,
+                TokenKind::StarEq,
+                TokenKind::SlashEq,
+                TokenKind::Plus,
+                TokenKind::Eq,
+                TokenKind::SlashEq,
+                TokenKind::Number(1.0),
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let ident = |s: &str| TokenKind::Ident(s.to_string());
+
+        assert_eq!(
+            kinds("café π_2 λx 変数1 and"),
+            vec![
+                ident("café"),
+                ident("π_2"),
+                ident("λx"),
+                ident("変数1"),
+                TokenKind::And,
+                TokenKind::Eof
+            ]
+        );
+
+        let tokens = Scanner::new("naïve + 1".to_string()).read_tokens().unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| (token.column, token.span))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, Span::new(0, 6)),
+                (7, Span::new(7, 8)),
+                (9, Span::new(9, 10)),
+                (10, Span::new(10, 10)),
+            ]
+        );
+
+        assert_eq!(
+            Scanner::new("a \u{301}b".to_string())
+                .read_tokens()
+                .unwrap_err()
+                .to_string(),
+            "Error interpreting line [1:3] \"a \u{301}b\": Unexpected character '\u{301}'"
+        );
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        assert_eq!(
+            kinds(r#"r"C:\new\${dir}" + r"" r rx r"#),
+            vec![
+                TokenKind::String(r"C:\new\${dir}".to_string()),
+                TokenKind::Plus,
+                TokenKind::String(String::new()),
+                TokenKind::Ident("r".to_string()),
+                TokenKind::Ident("rx".to_string()),
+                TokenKind::Ident("r".to_string()),
+                TokenKind::Eof
+            ]
+        );
+
+        // A raw string can't contain a quote, so `\"` ends it.
+        assert_eq!(
+            kinds(r#"r"a\" + 1"#),
+            vec![
+                TokenKind::String(r"a\".to_string()),
+                TokenKind::Plus,
+                TokenKind::Number(1.0),
+                TokenKind::Eof
+            ]
+        );
+
+        let tokens = Scanner::new("r\"one\ntwo\" 1".to_string())
+            .read_tokens()
+            .unwrap();
+        assert_eq!(tokens[0].kind, TokenKind::String("one\ntwo".to_string()));
+        assert_eq!(tokens[1].line, 2);
+
+        let err = Scanner::new("1 +\n  r\"one\\".to_string())
+            .read_tokens()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InterpretErrorWithIndex {
+                line_index: 2,
+                column: Some(3),
+                ref message,
+                ..
+            } if message == r#"Unterminated string starting at line 2, column 3: 'r"one\'"#
+        ));
+    }
+
+    #[test]
+    fn test_shebang() {
+        let tokens = Scanner::new("#!/usr/bin/env lox\n1 +\n  2".to_string())
+            .read_tokens()
+            .unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| (token.kind.clone(), token.line))
+                .collect::<Vec<_>>(),
+            vec![
+                (TokenKind::Number(1.0), 2),
+                (TokenKind::Plus, 2),
+                (TokenKind::Number(2.0), 3),
+                (TokenKind::Eof, 3)
+            ]
+        );
+
+        assert_eq!(kinds("#!/usr/bin/env lox"), vec![TokenKind::Eof]);
+
+        let err = Scanner::new("1\n#!/usr/bin/env lox".to_string())
+            .read_tokens()
+            .unwrap_err();
+        assert!(err.to_string().contains("[2:1]"));
+        assert!(err.to_string().ends_with("Unexpected character '#'"));
+        assert!(Scanner::new(" #!x".to_string()).read_tokens().is_err());
+    }
+
+    #[test]
+    fn test_trivia() {
+        let source = "// leading ✓\n1 /* inner /* nested */ */ + 2 // trailing\r\n/**/";
+        let tokens = Scanner::new(source.to_string())
+            .with_trivia(true)
+            .read_tokens()
+            .unwrap();
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| token.kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                TokenKind::Comment(" leading ✓".to_string()),
+                TokenKind::Number(1.0),
+                TokenKind::BlockComment(" inner /* nested */ ".to_string()),
+                TokenKind::Plus,
+                TokenKind::Number(2.0),
+                TokenKind::Comment(" trailing\r".to_string()),
+                TokenKind::BlockComment(String::new()),
+                TokenKind::Eof
+            ]
+        );
+
+        for token in tokens.iter().filter(|token| token.kind.is_trivia()) {
+            assert_eq!(&source[token.span.start..token.span.end], token.lexeme);
+            assert_eq!(token.kind.to_string(), token.lexeme);
+        }
+
+        assert_eq!(
+            tokens
+                .into_iter()
+                .filter(|token| !token.kind.is_trivia())
+                .map(|token| token.kind)
+                .collect::<Vec<_>>(),
+            kinds(source)
+        );
+    }
+
+    fn scan_streaming(source: &[u8], capacity: usize) -> (Vec<Token>, Vec<String>) {
+        let reader = io::BufReader::with_capacity(capacity, io::Cursor::new(source.to_vec()));
+        let result = Scanner::from_reader(reader).scan_all();
+        let errors = result.errors.iter().map(ToString::to_string).collect();
+
+        (result.tokens, errors)
+    }
+
+    #[test]
+    fn test_from_reader_matches_new() {
+        let sources: [&[u8]; 4] = [
+            "1 >= 2 << 3.5e+2 != \"héllo ✓ ${[1_000, \"x\"]} wörld\"\n\
+             /* a /* nested */ comment */ r\"raw\\\" // tail ü\n\
+             \"a string longer than the buffer,\nspanning lines\" 🦀 @\n\
+             \"unterminated ✓\nstring"
+                .as_bytes(),
+            b"\"bad \xff utf-8\" + \xe2\x9c",
+            b"#!/usr/bin/env lox\n1",
+            b"",
+        ];
+
+        for source in sources {
+            let result = Scanner::new(String::from_utf8_lossy(source).into_owned()).scan_all();
+            let errors = result
+                .errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+
+            for capacity in [1, 2, 3, 5, 64] {
+                assert_eq!(
+                    scan_streaming(source, capacity),
+                    (result.tokens.clone(), errors.clone()),
+                    "capacity {capacity}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_reader_buffers_little() {
+        let source = format!("{}@", "1 + 2 // filler\n".repeat(20_000));
+        let reader = io::BufReader::with_capacity(16, io::Cursor::new(source.into_bytes()));
+        let mut scanner = Scanner::from_reader(reader);
+        let mut tokens = 0;
+        let mut largest = 0;
+
+        while let Some(token) = scanner.next() {
+            largest = largest.max(scanner.source.len());
+
+            if let Err(err) = token {
+                assert_eq!(
+                    err.to_string(),
+                    "Error interpreting line [20001:1] \"@\": Unexpected character '@'"
+                );
+            } else {
+                tokens += 1;
+            }
+        }
+
+        assert_eq!(tokens, 60_001);
+        assert!(largest < 2 * COMPACT_BYTES, "buffered {largest} bytes");
+    }
+
+    #[test]
+    fn test_from_reader_read_error() {
+        struct Broken(bool);
+
+        impl io::Read for Broken {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Err(io::Error::other("disk on fire"));
+                }
+
+                buf[..4].copy_from_slice(b"1 + ");
+                Ok(4)
+            }
+        }
+
+        let result = Scanner::from_reader(io::BufReader::new(Broken(false))).scan_all();
+
+        assert_eq!(
+            result
+                .tokens
+                .into_iter()
+                .map(|token| token.kind)
+                .collect::<Vec<_>>(),
+            vec![TokenKind::Number(1.0), TokenKind::Plus, TokenKind::Eof]
+        );
+        assert_eq!(
+            result
+                .errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["disk on fire"]
+        );
+    }
 }